@@ -22,4 +22,7 @@ fn main() {
         .compile("libespresso.a");
 
     println!("cargo:rustc-link-lib=static={}", library_name);
+    /* cc emits rerun-if-env-changed, which disables the default rerun on any change */
+    println!("cargo:rerun-if-changed=src/verilog.lalrpop");
+    println!("cargo:rerun-if-changed=src/espresso-src");
 }
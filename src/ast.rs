@@ -1,32 +1,565 @@
 use std::{borrow::Cow, fmt::Debug, io};
 extern crate ptree;
 
+use crate::diagnostic::UserError;
 use ptree::{Style, TreeItem};
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOp {
+    /* ~ */
+    Not,
+    /* ! */
+    LogicNot,
+    /* reductions: &, ~&, |, ~|, ^, ~^ (also written ^~) */
+    And,
+    Nand,
+    Or,
+    Nor,
+    Xor,
+    Xnor,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOp {
+    LogicOr,
+    LogicAnd,
+    Or,
+    /* ^ and ~^ (also written ^~) */
+    Xor,
+    Xnor,
+    And,
+    /* ==, !=, ===, !== */
+    Eq,
+    Ne,
+    CaseEq,
+    CaseNe,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /* <<, >>, <<<, >>> */
+    Shl,
+    Shr,
+    AShl,
+    AShr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+/// Byte range `[start, end)` of a node in the parsed source.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// Bit or part picked out of a vector, every bound a constant expression.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Select {
+    /* [index] */
+    Bit(Box<Expr>),
+    /* [msb:lsb] */
+    Part(Box<Expr>, Box<Expr>),
+    /* [base +: width] */
+    Up(Box<Expr>, Box<Expr>),
+    /* [base -: width] */
+    Down(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExprKind {
+    Identifier(String),
+    /* ident[...] */
+    Select(String, Select),
+    /* literal text as written: 100, 8'hff, 'b101; a sign is an operator */
+    Number(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /* cond ? then : else */
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /* {a, b, c}, most significant part first */
+    Concat(Vec<Expr>),
+    /* {count{a, b}} */
+    Replicate(Box<Expr>, Vec<Expr>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, start: usize, end: usize) -> Self {
+        Expr {
+            kind,
+            span: Span::new(start, end),
+        }
+    }
+
+    pub fn unary(op: UnaryOp, operand: Expr, start: usize) -> Self {
+        let end = operand.span.end;
+        Expr::new(ExprKind::Unary(op, Box::new(operand)), start, end)
+    }
+
+    pub fn binary(op: BinaryOp, l: Expr, r: Expr) -> Self {
+        let span = l.span.to(r.span);
+        Expr {
+            kind: ExprKind::Binary(op, Box::new(l), Box::new(r)),
+            span,
+        }
+    }
+
+    pub fn conditional(cond: Expr, then: Expr, otherwise: Expr) -> Self {
+        let span = cond.span.to(otherwise.span);
+        Expr {
+            kind: ExprKind::Conditional(Box::new(cond), Box::new(then), Box::new(otherwise)),
+            span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// `[msb:lsb]`, both bounds constant expressions.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Range {
+    pub msb: Expr,
+    pub lsb: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Port {
+    pub direction: Direction,
+    pub range: Option<Range>,
+    pub name: String,
+    pub span: Span,
+}
+
+/// Entry of a module header: a port declaration, or just a name that is
+/// either declared in the body (non-ANSI) or continues the previous
+/// declaration (ANSI, `input [1:0] a, b`).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PortRef {
+    Declared(Port),
+    Name(String, Span),
+}
+
+/* declarations with several identifiers give one item per identifier */
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ItemKind {
+    /* non-ANSI `input`/`output` declaration in the module body */
+    Port(Port),
+    Wire(Option<Range>, String),
+    Reg(Option<Range>, String),
+    Assign(String, Expr),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Module {
+    pub name: String,
+    pub ports: Vec<Port>,
+    pub items: Vec<Item>,
+    pub span: Span,
+}
+
+impl Module {
+    /// Builds a module and resolves its header to one port per name, in
+    /// header order.
+    pub fn new(
+        name: String,
+        header: Vec<PortRef>,
+        items: Vec<Item>,
+        span: Span,
+    ) -> Result<Module, UserError> {
+        let declared = |name: &str| {
+            items.iter().find_map(|i| match &i.kind {
+                ItemKind::Port(p) if p.name == name => Some(p),
+                _ => None,
+            })
+        };
+        let ansi = matches!(header.first(), Some(PortRef::Declared(_)));
+        let mut ports: Vec<Port> = Vec::new();
+        for entry in header {
+            let port = match entry {
+                PortRef::Declared(p) if ansi => p,
+                PortRef::Declared(p) => {
+                    return Err(UserError {
+                        message: "cannot mix ANSI and non-ANSI port declarations",
+                        span: p.span,
+                    })
+                }
+                PortRef::Name(name, span) if ansi => {
+                    let previous = ports.last().unwrap();
+                    Port {
+                        direction: previous.direction,
+                        range: previous.range.clone(),
+                        name,
+                        span,
+                    }
+                }
+                PortRef::Name(name, span) => match declared(&name) {
+                    Some(p) => p.clone(),
+                    None => {
+                        return Err(UserError {
+                            message: "port has no input or output declaration",
+                            span,
+                        })
+                    }
+                },
+            };
+            if ports.iter().any(|p| p.name == port.name) {
+                return Err(UserError {
+                    message: "port is listed twice",
+                    span: port.span,
+                });
+            }
+            ports.push(port);
+        }
+
+        for item in items.iter() {
+            if let ItemKind::Port(p) = &item.kind {
+                if ansi || !ports.iter().any(|q| q.name == p.name) {
+                    return Err(UserError {
+                        message: "declared port is not in the port list",
+                        span: item.span,
+                    });
+                }
+            }
+        }
+        Ok(Module {
+            name,
+            ports,
+            items,
+            span,
+        })
+    }
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Not => "~",
+            UnaryOp::LogicNot => "!",
+            UnaryOp::And => "&",
+            UnaryOp::Nand => "~&",
+            UnaryOp::Or => "|",
+            UnaryOp::Nor => "~|",
+            UnaryOp::Xor => "^",
+            UnaryOp::Xnor => "~^",
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::LogicOr => "||",
+            BinaryOp::LogicAnd => "&&",
+            BinaryOp::Or => "|",
+            BinaryOp::Xor => "^",
+            BinaryOp::Xnor => "~^",
+            BinaryOp::And => "&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::CaseEq => "===",
+            BinaryOp::CaseNe => "!==",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::AShl => "<<<",
+            BinaryOp::AShr => ">>>",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+        }
+    }
+}
+
+/// Printable view of the AST, one label per node.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct TreeNode {
-    pub tag: String,
     pub val: String,
     pub subs: Vec<TreeNode>,
 }
 
 impl TreeNode {
-    pub fn new(tag: &'static str, val: String, subs: Vec<TreeNode>) -> Self {
+    pub fn new(val: &str, subs: Vec<TreeNode>) -> Self {
         TreeNode {
-            tag: tag.to_string(),
-            val,
+            val: val.to_string(),
             subs,
         }
     }
 }
 
+impl From<&Expr> for TreeNode {
+    fn from(value: &Expr) -> Self {
+        match &value.kind {
+            ExprKind::Identifier(i) | ExprKind::Number(i) => TreeNode::new(i, vec![]),
+            ExprKind::Select(i, select) => {
+                let name = TreeNode::new(i, vec![]);
+                match select {
+                    Select::Bit(n) => TreeNode::new("[]", vec![name, TreeNode::from(n.as_ref())]),
+                    Select::Part(m, l) | Select::Up(m, l) | Select::Down(m, l) => {
+                        let val = match select {
+                            Select::Part(_, _) => "[:]",
+                            Select::Up(_, _) => "[+:]",
+                            _ => "[-:]",
+                        };
+                        TreeNode::new(
+                            val,
+                            vec![name, TreeNode::from(m.as_ref()), TreeNode::from(l.as_ref())],
+                        )
+                    }
+                }
+            }
+            ExprKind::Unary(op, e) => TreeNode::new(op.symbol(), vec![TreeNode::from(e.as_ref())]),
+            ExprKind::Binary(op, l, r) => TreeNode::new(
+                op.symbol(),
+                vec![TreeNode::from(l.as_ref()), TreeNode::from(r.as_ref())],
+            ),
+            ExprKind::Conditional(c, t, f) => TreeNode::new(
+                "?:",
+                vec![
+                    TreeNode::from(c.as_ref()),
+                    TreeNode::from(t.as_ref()),
+                    TreeNode::from(f.as_ref()),
+                ],
+            ),
+            ExprKind::Concat(l) => TreeNode::new("{}", l.iter().map(TreeNode::from).collect()),
+            ExprKind::Replicate(n, l) => TreeNode::new(
+                "{{}}",
+                vec![
+                    TreeNode::from(n.as_ref()),
+                    TreeNode::new("{}", l.iter().map(TreeNode::from).collect()),
+                ],
+            ),
+        }
+    }
+}
+
+impl From<&Range> for TreeNode {
+    fn from(value: &Range) -> Self {
+        TreeNode::new(
+            "[:]",
+            vec![TreeNode::from(&value.msb), TreeNode::from(&value.lsb)],
+        )
+    }
+}
+
+impl From<&Port> for TreeNode {
+    fn from(value: &Port) -> Self {
+        let direction = match value.direction {
+            Direction::Input => "input",
+            Direction::Output => "output",
+        };
+        let mut subs: Vec<TreeNode> = Vec::new();
+        if let Some(r) = &value.range {
+            subs.push(TreeNode::from(r));
+        }
+        subs.push(TreeNode::new(&value.name, vec![]));
+        TreeNode::new(direction, subs)
+    }
+}
+
+impl From<&Item> for TreeNode {
+    fn from(value: &Item) -> Self {
+        match &value.kind {
+            ItemKind::Port(p) => TreeNode::from(p),
+            ItemKind::Wire(r, i) | ItemKind::Reg(r, i) => {
+                let val = match value.kind {
+                    ItemKind::Wire(_, _) => "Wire",
+                    _ => "Reg",
+                };
+                let mut subs: Vec<TreeNode> = Vec::new();
+                if let Some(r) = r {
+                    subs.push(TreeNode::from(r));
+                }
+                subs.push(TreeNode::new(i, vec![]));
+                TreeNode::new(val, subs)
+            }
+            ItemKind::Assign(i, e) => {
+                TreeNode::new("Assign", vec![TreeNode::new(i, vec![]), TreeNode::from(e)])
+            }
+        }
+    }
+}
+
+impl From<&Module> for TreeNode {
+    fn from(value: &Module) -> Self {
+        TreeNode::new(
+            "module",
+            vec![
+                TreeNode::new(&value.name, vec![]),
+                TreeNode::new(",", value.ports.iter().map(TreeNode::from).collect()),
+                TreeNode::new("block", value.items.iter().map(TreeNode::from).collect()),
+            ],
+        )
+    }
+}
+
 impl TreeItem for TreeNode {
     type Child = Self;
     fn write_self<W: io::Write>(&self, f: &mut W, style: &Style) -> io::Result<()> {
         write!(f, "{}", style.paint(&self.val))
     }
 
-    fn children(&self) -> Cow<[Self::Child]> {
+    fn children(&self) -> Cow<'_, [Self::Child]> {
         Cow::from(&self.subs)
     }
 }
+
+impl TreeItem for Expr {
+    type Child = TreeNode;
+    fn write_self<W: io::Write>(&self, f: &mut W, style: &Style) -> io::Result<()> {
+        TreeNode::from(self).write_self(f, style)
+    }
+
+    fn children(&self) -> Cow<'_, [Self::Child]> {
+        Cow::from(TreeNode::from(self).subs)
+    }
+}
+
+impl TreeItem for Module {
+    type Child = TreeNode;
+    fn write_self<W: io::Write>(&self, f: &mut W, style: &Style) -> io::Result<()> {
+        TreeNode::from(self).write_self(f, style)
+    }
+
+    fn children(&self) -> Cow<'_, [Self::Child]> {
+        Cow::from(TreeNode::from(self).subs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Direction, ExprKind, ItemKind, TreeNode};
+    use crate::verilog;
+
+    #[test]
+    fn test_module_ports() {
+        let parse = |s: &str| verilog::Module_scopeParser::new().parse(s).ok();
+
+        /* ANSI names continue the previous declaration */
+        let m = parse("module m(input [3:0] a, b, output wire y); endmodule").unwrap();
+        let ports: Vec<(&str, Direction, bool)> = m
+            .ports
+            .iter()
+            .map(|p| (p.name.as_str(), p.direction, p.range.is_some()))
+            .collect();
+        assert_eq!(
+            ports,
+            [
+                ("a", Direction::Input, true),
+                ("b", Direction::Input, true),
+                ("y", Direction::Output, false)
+            ]
+        );
+
+        /* non-ANSI ports keep the header order, not the declaration order */
+        let m = parse(
+            "module m(y, a, b);
+                output y;
+                input [1:0] a, b;
+                wire [1:0] t, u;
+                reg r;
+                assign y = a[0];
+            endmodule",
+        )
+        .unwrap();
+        let names: Vec<&str> = m.ports.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["y", "a", "b"]);
+        assert_eq!(m.ports[1].direction, Direction::Input);
+        assert_eq!(m.items.len(), 7);
+        assert!(matches!(&m.items[3].kind, ItemKind::Wire(Some(_), t) if t == "t"));
+        assert!(matches!(&m.items[5].kind, ItemKind::Reg(None, _)));
+
+        assert!(parse("module m; endmodule").unwrap().ports.is_empty());
+        assert!(parse("module m(a, y); input a; endmodule").is_none());
+        assert!(parse("module m(a); input a, b; endmodule").is_none());
+        assert!(parse("module m(a, output y); input a; endmodule").is_none());
+        assert!(parse("module m(input a); input b; endmodule").is_none());
+        assert!(parse("module m(input a) { }").is_none());
+    }
+
+    #[test]
+    fn test_comments_and_identifiers() {
+        let tree = verilog::ExprParser::new()
+            .parse("\\bus[0] /* escaped */ & clk$gated // trailing")
+            .unwrap();
+        let names: Vec<String> = TreeNode::from(&tree)
+            .subs
+            .iter()
+            .map(|n| n.val.clone())
+            .collect();
+        assert_eq!(names, ["\\bus[0] ", "clk$gated"]);
+        assert!(matches!(tree.kind, ExprKind::Binary(_, _, _)));
+
+        let m = verilog::Module_scopeParser::new()
+            .parse(
+                "// header comment
+                module m(input a, /* b, */ output y);
+                    assign y = a; // a*/b
+                endmodule",
+            )
+            .unwrap();
+        assert_eq!(m.ports.len(), 2);
+    }
+
+    #[test]
+    fn test_sign_operators() {
+        let parse = |s: &str| TreeNode::from(&verilog::ExprParser::new().parse(s).unwrap());
+
+        /* + and - next to a number are binary operators */
+        assert_eq!(parse("a+1"), parse("a + 1"));
+        assert_eq!(parse("a-1"), parse("a - 1"));
+        assert_eq!(parse("x[3:0]+1"), parse("x[3:0] + 1"));
+        assert_eq!(parse("a-1").val, "-");
+        assert_eq!(parse("a+-1"), parse("a + (1'b0 - 1)"));
+        assert_eq!(parse("-a"), parse("1'b0 - a"));
+        assert_eq!(parse("+a"), parse("a"));
+    }
+
+    #[test]
+    fn test_nested_unary() {
+        let parse = |s: &str| TreeNode::from(&verilog::ExprParser::new().parse(s).unwrap());
+
+        /* unary operators apply to unary operands */
+        assert_eq!(parse("~~a"), parse("~(~a)"));
+        assert_eq!(parse("!~a"), parse("!(~a)"));
+        assert_eq!(parse("&~a"), parse("&(~a)"));
+        assert_eq!(parse("~-a"), parse("~(1'b0 - a)"));
+        assert_eq!(parse("a & ~&b"), parse("a & (~&b)"));
+        assert_eq!(parse("~a + b").val, "+");
+    }
+}
@@ -1,170 +1,557 @@
-use crate::ast::TreeNode;
-use lazy_static::lazy_static;
+use crate::espresso::{Cover, Cube};
 use std::collections::HashMap;
-use std::convert::From;
-use std::sync::Mutex;
-
-lazy_static! {
-    pub static ref MAP: Mutex<HashMap<String, bool>> = {
-        #![allow(unused_mut)]
-        let mut map = HashMap::new();
-        Mutex::new(map)
-    };
+
+/// Edge into the shared BDD graph. The lowest bit marks a complement edge,
+/// the remaining bits index the node table of the owning `Manager`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Bdd(u32);
+
+impl Bdd {
+    pub const TRUE: Bdd = Bdd(0);
+    pub const FALSE: Bdd = Bdd(1);
+
+    fn index(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    fn is_complement(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    pub fn is_const(self) -> bool {
+        self.index() == 0
+    }
 }
 
-#[allow(dead_code)]
-pub struct BNode {
-    value: bool,
-    is_leave: bool,
-    operator: String,
-    method: Option<Box<dyn Fn(bool, bool) -> bool>>,
-    left: Option<Box<BNode>>,
-    right: Option<Box<BNode>>,
+impl std::ops::Not for Bdd {
+    type Output = Bdd;
+    fn not(self) -> Bdd {
+        Bdd(self.0 ^ 1)
+    }
 }
 
-impl From<&TreeNode> for BNode {
-    fn from(value: &TreeNode) -> Self {
-        let mut bnode = BNode {
-            value: false,
-            is_leave: false,
-            operator: value.tag.clone(),
-            method: None,
-            left: None,
-            right: None,
-        };
+/* sparse cube used while building covers: (variable, phase) pairs */
+type Literals = Vec<(u32, bool)>;
+type IsopCache = HashMap<(Bdd, Bdd), (Vec<Literals>, Bdd)>;
+
+/* the terminal node uses the largest level so it sorts below every variable */
+const TERMINAL: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+struct Node {
+    var: u32,
+    low: Bdd,
+    high: Bdd,
+}
+
+/// Reduced ordered BDD package with complement edges.
+///
+/// Variables are ordered by creation, and the `high` edge of a stored node is
+/// never complemented, which keeps every function canonical: two equivalent
+/// functions built in the same manager always get the same `Bdd`.
+pub struct Manager {
+    nodes: Vec<Node>,
+    unique: HashMap<(u32, Bdd, Bdd), Bdd>,
+    computed: HashMap<(Bdd, Bdd, Bdd), Bdd>,
+    names: Vec<String>,
+}
+
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        Manager {
+            nodes: vec![Node {
+                var: TERMINAL,
+                low: Bdd::TRUE,
+                high: Bdd::TRUE,
+            }],
+            unique: HashMap::new(),
+            computed: HashMap::new(),
+            names: Vec::new(),
+        }
+    }
+
+    pub fn constant(&self, value: bool) -> Bdd {
+        if value {
+            Bdd::TRUE
+        } else {
+            Bdd::FALSE
+        }
+    }
 
-        let func: Option<Box<dyn Fn(bool, bool) -> bool>> = match value.tag.to_lowercase().as_str()
-        {
-            "s~" | "s!" => Some(Box::new(|a: bool, _b: bool| !a)),
-            "d+" => Some(Box::new(|a: bool, b: bool| a | b)),
-            "d-" => Some(Box::new(|a: bool, b: bool| a != b)),
-            "d&" => Some(Box::new(|a: bool, b: bool| a & b)),
-            "d|" => Some(Box::new(|a: bool, b: bool| a | b)),
-            "d&&" => Some(Box::new(|a: bool, b: bool| a & b)),
-            "d||" => Some(Box::new(|a: bool, b: bool| a | b)),
-            "identifier" => {
-                bnode.is_leave = true;
-                bnode.operator = value.val.clone();
-                MAP.lock().unwrap().insert(value.val.clone(), false);
-                Some(Box::new(|a: bool, _b: bool| a))
+    /// Returns the projection function of `name`, creating the variable
+    /// below all existing ones on first use.
+    pub fn var(&mut self, name: &str) -> Bdd {
+        let index = match self.names.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
             }
-            _ => None,
         };
+        self.mk(index as u32, Bdd::FALSE, Bdd::TRUE)
+    }
+
+    pub fn var_names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn num_vars(&self) -> usize {
+        self.names.len()
+    }
+
+    fn level(&self, f: Bdd) -> u32 {
+        self.nodes[f.index()].var
+    }
+
+    /* cofactors of f with respect to the variable at `var` */
+    fn cofactors(&self, f: Bdd, var: u32) -> (Bdd, Bdd) {
+        let node = self.nodes[f.index()];
+        if node.var != var {
+            return (f, f);
+        }
+        if f.is_complement() {
+            (!node.low, !node.high)
+        } else {
+            (node.low, node.high)
+        }
+    }
+
+    fn mk(&mut self, var: u32, low: Bdd, high: Bdd) -> Bdd {
+        if low == high {
+            return low;
+        }
+        /* canonical form: the high edge is always regular */
+        if high.is_complement() {
+            return !self.mk(var, !low, !high);
+        }
+        if let Some(f) = self.unique.get(&(var, low, high)) {
+            return *f;
+        }
+        let f = Bdd((self.nodes.len() as u32) << 1);
+        self.nodes.push(Node { var, low, high });
+        self.unique.insert((var, low, high), f);
+        f
+    }
+
+    /// If-then-else, the single primitive every other operator is built on.
+    pub fn ite(&mut self, f: Bdd, g: Bdd, h: Bdd) -> Bdd {
+        if f == Bdd::TRUE {
+            return g;
+        }
+        if f == Bdd::FALSE {
+            return h;
+        }
+        if g == h {
+            return g;
+        }
+        if g == Bdd::TRUE && h == Bdd::FALSE {
+            return f;
+        }
+        if g == Bdd::FALSE && h == Bdd::TRUE {
+            return !f;
+        }
+
+        /* normalize the triple so complemented variants share cache entries */
+        let (f, g, h) = if f.is_complement() {
+            (!f, h, g)
+        } else {
+            (f, g, h)
+        };
+        let (g, h, negate) = if g.is_complement() {
+            (!g, !h, true)
+        } else {
+            (g, h, false)
+        };
+
+        if let Some(r) = self.computed.get(&(f, g, h)) {
+            return if negate { !*r } else { *r };
+        }
+
+        let top = self.level(f).min(self.level(g)).min(self.level(h));
+        let (f0, f1) = self.cofactors(f, top);
+        let (g0, g1) = self.cofactors(g, top);
+        let (h0, h1) = self.cofactors(h, top);
+        let low = self.ite(f0, g0, h0);
+        let high = self.ite(f1, g1, h1);
+        let r = self.mk(top, low, high);
 
-        if func.is_none() {
-            panic!("Unsupported operator in expression!");
+        self.computed.insert((f, g, h), r);
+        if negate {
+            !r
+        } else {
+            r
         }
+    }
+
+    pub fn and(&mut self, f: Bdd, g: Bdd) -> Bdd {
+        self.ite(f, g, Bdd::FALSE)
+    }
+
+    pub fn or(&mut self, f: Bdd, g: Bdd) -> Bdd {
+        self.ite(f, Bdd::TRUE, g)
+    }
+
+    pub fn xor(&mut self, f: Bdd, g: Bdd) -> Bdd {
+        self.ite(f, !g, g)
+    }
 
-        bnode.method = func;
-        match value.subs.len() {
-            1 => {
-                bnode.left = Some(Box::new(BNode::from(&value.subs[0])));
-                bnode.right = Some(Box::new(BNode::from(&value.subs[0])));
+    pub fn xnor(&mut self, f: Bdd, g: Bdd) -> Bdd {
+        self.ite(f, g, !g)
+    }
+
+    /// Number of distinct nodes reachable from `f`, terminal included.
+    pub fn node_count(&self, f: Bdd) -> usize {
+        let mut seen: Vec<bool> = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = vec![f.index()];
+        let mut count = 0;
+        while let Some(i) = stack.pop() {
+            if seen[i] {
+                continue;
             }
-            2 => {
-                bnode.left = Some(Box::new(BNode::from(&value.subs[0])));
-                bnode.right = Some(Box::new(BNode::from(&value.subs[1])));
+            seen[i] = true;
+            count += 1;
+            if i != 0 {
+                stack.push(self.nodes[i].low.index());
+                stack.push(self.nodes[i].high.index());
             }
-            _ => (),
         }
+        count
+    }
 
-        bnode
+    /// Number of satisfying assignments over all variables of the manager.
+    pub fn sat_count(&self, f: Bdd) -> f64 {
+        let mut cache: HashMap<Bdd, f64> = HashMap::new();
+        /* fraction of the whole space on which f is true */
+        let density = self.density(f, &mut cache);
+        density * 2f64.powi(self.num_vars() as i32)
     }
-}
 
-impl BNode {
-    pub fn eval(&self) -> Option<bool> {
-        if self.is_leave {
-            Some(*MAP.lock().unwrap().get(&self.operator).unwrap())
-        } else {
-            self.method.as_ref().map(|m| {
-                m(
-                    self.left.as_ref().unwrap().eval().unwrap(),
-                    self.right.as_ref().unwrap().eval().unwrap(),
-                )
-            })
+    fn density(&self, f: Bdd, cache: &mut HashMap<Bdd, f64>) -> f64 {
+        if f == Bdd::TRUE {
+            return 1.0;
+        }
+        if f == Bdd::FALSE {
+            return 0.0;
+        }
+        if let Some(d) = cache.get(&f) {
+            return *d;
         }
+        let (low, high) = self.cofactors(f, self.level(f));
+        let d = (self.density(low, cache) + self.density(high, cache)) / 2.0;
+        cache.insert(f, d);
+        d
+    }
+
+    /// Returns one satisfying assignment of `f`, with `None` marking the
+    /// variables left free by the chosen path.
+    pub fn sat_one(&self, f: Bdd) -> Option<Vec<Option<bool>>> {
+        if f == Bdd::FALSE {
+            return None;
+        }
+        let mut cube: Vec<Option<bool>> = vec![None; self.num_vars()];
+        let mut f = f;
+        while !f.is_const() {
+            let var = self.level(f);
+            let (low, high) = self.cofactors(f, var);
+            if low != Bdd::FALSE {
+                cube[var as usize] = Some(false);
+                f = low;
+            } else {
+                cube[var as usize] = Some(true);
+                f = high;
+            }
+        }
+        Some(cube)
+    }
+
+    /// Irredundant sum-of-products cover of `f` (Minato-Morreale), one
+    /// entry per cube with `None` for the variables the cube does not use.
+    pub fn cubes(&mut self, f: Bdd) -> Vec<Vec<Option<bool>>> {
+        let mut cache: IsopCache = HashMap::new();
+        let (cover, _) = self.isop(f, f, &mut cache);
+        cover
+            .iter()
+            .map(|literals| {
+                let mut cube: Vec<Option<bool>> = vec![None; self.num_vars()];
+                for (var, value) in literals.iter() {
+                    cube[*var as usize] = Some(*value);
+                }
+                cube
+            })
+            .collect()
     }
 
-    pub fn create_truthtable(&self) -> (Vec<String>, Vec<String>) {
-        let len: usize = MAP.lock().unwrap().len();
-        let stop_mask: usize = (1 << len) - 1;
-        let mut ret: Vec<String> = Vec::new();
+    /* cover of some function between `lower` and `upper`, plus that function */
+    fn isop(&mut self, lower: Bdd, upper: Bdd, cache: &mut IsopCache) -> (Vec<Literals>, Bdd) {
+        if lower == Bdd::FALSE {
+            return (Vec::new(), Bdd::FALSE);
+        }
+        if upper == Bdd::TRUE {
+            return (vec![Vec::new()], Bdd::TRUE);
+        }
+        if let Some(r) = cache.get(&(lower, upper)) {
+            return r.clone();
+        }
+
+        let var = self.level(lower).min(self.level(upper));
+        let (l0, l1) = self.cofactors(lower, var);
+        let (u0, u1) = self.cofactors(upper, var);
 
-        let list: Vec<String> = MAP.lock().unwrap().keys().cloned().collect();
-        println!("{:?}", list);
+        /* minterms that can only be covered with the literal x' or x */
+        let only0 = self.and(l0, !u1);
+        let (cover0, f0) = self.isop(only0, u0, cache);
+        let only1 = self.and(l1, !u0);
+        let (cover1, f1) = self.isop(only1, u1, cache);
 
-        ret.push(format!(".i {}", list.len()));
-        ret.push(String::from(".o 1"));
+        /* the rest is covered by cubes independent of x */
+        let rest0 = self.and(l0, !f0);
+        let rest1 = self.and(l1, !f1);
+        let rest = self.or(rest0, rest1);
+        let shared = self.and(u0, u1);
+        let (cover_star, f_star) = self.isop(rest, shared, &mut *cache);
 
-        for mut i in 0..=stop_mask {
-            for (j, item) in list.iter().enumerate() {
-                let mask: usize = 1 << (list.len() - 1 - j);
-                MAP.lock()
-                    .unwrap()
-                    // repair here, to reduce the memory usage
-                    .entry(item.clone())
-                    .and_modify(|v| *v = (mask & i) > 0);
+        let mut cover: Vec<Literals> = Vec::new();
+        for (c, value) in [(cover0, false), (cover1, true)] {
+            for mut literals in c {
+                literals.push((var, value));
+                cover.push(literals);
             }
-            let result: bool = self.eval().unwrap();
+        }
+        cover.extend(cover_star);
+
+        let x = self.mk(var, Bdd::FALSE, Bdd::TRUE);
+        let split = self.ite(x, f1, f0);
+        let f = self.or(split, f_star);
+
+        cache.insert((lower, upper), (cover.clone(), f));
+        (cover, f)
+    }
+
+    /// Cofactor of `f` with variable `var` fixed to `value`.
+    pub fn restrict(&mut self, f: Bdd, var: usize, value: bool) -> Bdd {
+        let mut cache: HashMap<Bdd, Bdd> = HashMap::new();
+        self.restrict_rec(f, var as u32, value, &mut cache)
+    }
+
+    fn restrict_rec(
+        &mut self,
+        f: Bdd,
+        var: u32,
+        value: bool,
+        cache: &mut HashMap<Bdd, Bdd>,
+    ) -> Bdd {
+        let level = self.level(f);
+        if level > var {
+            return f;
+        }
+        if level == var {
+            let (low, high) = self.cofactors(f, var);
+            return if value { high } else { low };
+        }
+        /* restriction commutes with complement, cache the regular edge */
+        if f.is_complement() {
+            return !self.restrict_rec(!f, var, value, cache);
+        }
+        if let Some(r) = cache.get(&f) {
+            return *r;
+        }
+        let (low, high) = self.cofactors(f, level);
+        let low = self.restrict_rec(low, var, value, cache);
+        let high = self.restrict_rec(high, var, value, cache);
+        let r = self.mk(level, low, high);
+        cache.insert(f, r);
+        r
+    }
 
-            i <<= 1;
+    /// `f` is true for some value of the variables `vars`.
+    pub fn exists(&mut self, f: Bdd, vars: &[usize]) -> Bdd {
+        vars.iter().fold(f, |f, v| {
+            let low = self.restrict(f, *v, false);
+            let high = self.restrict(f, *v, true);
+            self.or(low, high)
+        })
+    }
+
+    /// `f` is true for every value of the variables `vars`.
+    pub fn forall(&mut self, f: Bdd, vars: &[usize]) -> Bdd {
+        !self.exists(!f, vars)
+    }
+
+    pub fn eval(&self, f: Bdd, assignment: &[bool]) -> bool {
+        let mut f = f;
+        while !f.is_const() {
+            let var = self.level(f);
+            let (low, high) = self.cofactors(f, var);
+            f = if assignment[var as usize] { high } else { low };
+        }
+        f == Bdd::TRUE
+    }
+
+    /// Indices of the variables `f` actually depends on, in order.
+    pub fn support(&self, f: Bdd) -> Vec<usize> {
+        let mut seen: Vec<bool> = vec![false; self.nodes.len()];
+        let mut used: Vec<bool> = vec![false; self.num_vars()];
+        let mut stack: Vec<usize> = vec![f.index()];
+        while let Some(i) = stack.pop() {
+            if seen[i] || i == 0 {
+                continue;
+            }
+            seen[i] = true;
+            used[self.nodes[i].var as usize] = true;
+            stack.push(self.nodes[i].low.index());
+            stack.push(self.nodes[i].high.index());
+        }
+        (0..self.num_vars()).filter(|v| used[*v]).collect()
+    }
 
-            if result {
-                i |= 1;
+    /// Multi-output Espresso input for the on-sets `fs` and don't-care
+    /// sets `dcs`, built from the cube cover of every function instead of
+    /// the full truth table. Only the union of their supports becomes PLA
+    /// inputs, so variables removed by constant propagation never reach
+    /// Espresso, and a cube shared by several functions is a single row.
+    /// Returns the on-set cover, the don't-care cover and the input labels
+    /// in order.
+    pub fn create_cover(&mut self, fs: &[Bdd], dcs: &[Bdd]) -> (Cover, Cover, Vec<String>) {
+        let mut used: Vec<bool> = vec![false; self.num_vars()];
+        for f in fs.iter().chain(dcs.iter()) {
+            for v in self.support(*f) {
+                used[v] = true;
             }
+        }
+        let support: Vec<usize> = (0..self.num_vars()).filter(|v| used[*v]).collect();
+        let list: Vec<String> = support.iter().map(|v| self.names[*v].clone()).collect();
+
+        let on_set = self.cover_of(fs, &support);
+        let dc_set = self.cover_of(dcs, &support);
+        (on_set, dc_set, list)
+    }
 
-            ret.push(format!(
-                "{:0width$b} {:1b}",
-                i >> 1,
-                i & 1,
-                width = list.len()
-            ));
+    /* one row per distinct cube over `support`, in order of first appearance */
+    fn cover_of(&mut self, fs: &[Bdd], support: &[usize]) -> Cover {
+        let mut cover = Cover::new(support.len(), fs.len());
+        let mut index: HashMap<Vec<Option<bool>>, usize> = HashMap::new();
+        for (output, f) in fs.iter().enumerate() {
+            for cube in self.cubes(*f).iter() {
+                let inputs: Vec<Option<bool>> = support.iter().map(|v| cube[*v]).collect();
+                let i = *index.entry(inputs.clone()).or_insert_with(|| {
+                    cover.cubes.push(Cube {
+                        inputs,
+                        outputs: vec![false; fs.len()],
+                        pairs: Vec::new(),
+                    });
+                    cover.cubes.len() - 1
+                });
+                cover.cubes[i].outputs[output] = true;
+            }
         }
-        ret.push(String::from(".e"));
-        (ret, list)
+        cover
     }
 
-    pub fn get_inputs(&self) -> &MAP {
-        &MAP
+    /// The on-set cover of `fs` written out as the lines of a PLA file.
+    pub fn create_truthtable(&mut self, fs: &[Bdd]) -> (Vec<String>, Vec<String>) {
+        let (cover, _, list) = self.create_cover(fs, &[]);
+        (cover.to_pla(), list)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::bdd::BNode;
-    use crate::bdd::MAP;
+    use crate::bdd::{Bdd, Manager};
+
     #[test]
-    fn test_bnode() {
-        let bnode_left = BNode {
-            value: false,
-            is_leave: true,
-            operator: String::from("a"),
-            method: None,
-            left: None,
-            right: None,
-        };
-        let bnode_right = BNode {
-            value: false,
-            is_leave: true,
-            operator: String::from("b"),
-            method: None,
-            left: None,
-            right: None,
-        };
+    fn test_canonical() {
+        let mut m = Manager::new();
+        let a = m.var("a");
+        let b = m.var("b");
+        let c = m.var("c");
 
-        let bnode_root = BNode {
-            value: false,
-            is_leave: false,
-            operator: String::from("2&"),
-            method: Some(Box::new(|a: bool, b: bool| a & b)),
-            left: Some(Box::new(bnode_left)),
-            right: Some(Box::new(bnode_right)),
-        };
+        /* a & (b | c) == (a & b) | (a & c) */
+        let bc = m.or(b, c);
+        let lhs = m.and(a, bc);
+        let ab = m.and(a, b);
+        let ac = m.and(a, c);
+        let rhs = m.or(ab, ac);
+        assert_eq!(lhs, rhs);
+
+        /* De Morgan only flips complement bits */
+        let nand = !m.and(a, b);
+        let or_n = m.or(!a, !b);
+        assert_eq!(nand, or_n);
+
+        let x = m.xor(a, a);
+        assert_eq!(x, Bdd::FALSE);
+        assert_eq!(m.sat_count(lhs), 3.0);
+    }
+
+    #[test]
+    fn test_wide_function() {
+        /* (x0 & y0) | (x1 & y1) | ... over 80 interleaved variables */
+        let mut m = Manager::new();
+        let mut f = Bdd::FALSE;
+        for i in 0..40 {
+            let x = m.var(&format!("x{}", i));
+            let y = m.var(&format!("y{}", i));
+            let term = m.and(x, y);
+            f = m.or(f, term);
+        }
+        assert_eq!(m.num_vars(), 80);
+        assert_eq!(m.node_count(f), 81);
+        assert_eq!(m.cubes(f).len(), 40);
 
-        MAP.lock().unwrap().insert(String::from("a"), true);
-        MAP.lock().unwrap().insert(String::from("b"), false);
+        let mut assignment = vec![false; 80];
+        assert!(!m.eval(f, &assignment));
+        assignment[2 * 17] = true;
+        assignment[2 * 17 + 1] = true;
+        assert!(m.eval(f, &assignment));
+
+        let witness: Vec<bool> = m
+            .sat_one(!f)
+            .unwrap()
+            .iter()
+            .map(|v| v.unwrap_or(false))
+            .collect();
+        assert!(!m.eval(f, &witness));
+    }
+
+    #[test]
+    fn test_multi_output_table() {
+        let mut m = Manager::new();
+        let a = m.var("a");
+        let _unused = m.var("u");
+        let b = m.var("b");
+        let c = m.var("c");
+
+        /* a&b is a cube of both functions and becomes one row */
+        let ab = m.and(a, b);
+        let f = m.or(ab, c);
+        let (table, inputs) = m.create_truthtable(&[ab, f, Bdd::FALSE]);
+        assert_eq!(inputs, ["a", "b", "c"]);
+        assert_eq!(table[..2], [".i 3", ".o 3"]);
+        assert!(table.contains(&String::from("11- 110")));
+        assert!(table.contains(&String::from("--1 010")));
+        assert_eq!(table.len(), 5);
+    }
+
+    #[test]
+    fn test_quantification() {
+        let mut m = Manager::new();
+        let a = m.var("a");
+        let x = m.var("x");
+        let b = m.var("b");
 
-        assert_eq!(bnode_root.eval(), Some(false));
+        /* (a & x) | b */
+        let ax = m.and(a, x);
+        let f = m.or(ax, b);
+        let a_or_b = m.or(a, b);
+        assert_eq!(m.exists(f, &[1]), a_or_b);
+        assert_eq!(m.forall(f, &[1]), b);
+        assert_eq!(m.restrict(!f, 1, true), !a_or_b);
+        assert_eq!(m.exists(f, &[0, 1, 2]), Bdd::TRUE);
     }
 }
@@ -0,0 +1,188 @@
+//! Gate-level lowering of the arithmetic and selection operators. Every
+//! function works on LSB-first bit vectors of equal width and returns a
+//! vector of that width, so the result wraps around exactly like a Verilog
+//! expression of that size.
+
+use crate::bdd::{Bdd, Manager};
+
+/* (sum, carry) of a full adder */
+fn full_adder(m: &mut Manager, a: Bdd, b: Bdd, c: Bdd) -> (Bdd, Bdd) {
+    let ab = m.xor(a, b);
+    let sum = m.xor(ab, c);
+    let b_or_c = m.or(b, c);
+    let b_and_c = m.and(b, c);
+    let carry = m.ite(a, b_or_c, b_and_c);
+    (sum, carry)
+}
+
+/* ripple-carry chain returning the sum and the final carry */
+fn ripple(m: &mut Manager, a: &[Bdd], b: &[Bdd], carry_in: Bdd) -> (Vec<Bdd>, Bdd) {
+    let mut carry = carry_in;
+    let mut sum: Vec<Bdd> = Vec::with_capacity(a.len());
+    for (x, y) in a.iter().zip(b.iter()) {
+        let (s, c) = full_adder(m, *x, *y, carry);
+        sum.push(s);
+        carry = c;
+    }
+    (sum, carry)
+}
+
+/// 2:1 multiplexer per bit: `sel ? a : b`.
+pub fn mux(m: &mut Manager, sel: Bdd, a: &[Bdd], b: &[Bdd]) -> Vec<Bdd> {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| m.ite(sel, *x, *y))
+        .collect()
+}
+
+/// `a == b` as a single bit.
+pub fn equal(m: &mut Manager, a: &[Bdd], b: &[Bdd]) -> Bdd {
+    a.iter().zip(b.iter()).fold(Bdd::TRUE, |acc, (x, y)| {
+        let same = m.xnor(*x, *y);
+        m.and(acc, same)
+    })
+}
+
+/// Unsigned `a < b`: the subtraction `a - b` borrows out of the top bit.
+pub fn less_than(m: &mut Manager, a: &[Bdd], b: &[Bdd]) -> Bdd {
+    let not_b: Vec<Bdd> = b.iter().map(|x| !*x).collect();
+    !ripple(m, a, &not_b, Bdd::TRUE).1
+}
+
+/* one stage of 2:1 multiplexers per bit of `amount`, stage k moving the
+ * vector by 2^k; vacated positions take `fill` */
+fn barrel(m: &mut Manager, a: &[Bdd], amount: &[Bdd], fill: Bdd, left: bool) -> Vec<Bdd> {
+    let width = a.len();
+    let mut bits: Vec<Bdd> = a.to_vec();
+    for (k, s) in amount.iter().enumerate() {
+        let distance = 1usize.checked_shl(k as u32).filter(|d| *d < width);
+        let shifted: Vec<Bdd> = (0..width)
+            .map(|i| match distance {
+                None => fill,
+                Some(d) if left => i.checked_sub(d).map_or(fill, |j| bits[j]),
+                Some(d) => bits.get(i + d).copied().unwrap_or(fill),
+            })
+            .collect();
+        bits = mux(m, *s, &shifted, &bits);
+    }
+    bits
+}
+
+/// Barrel shifter for `a << amount`, zeros are shifted in.
+pub fn shift_left(m: &mut Manager, a: &[Bdd], amount: &[Bdd]) -> Vec<Bdd> {
+    barrel(m, a, amount, Bdd::FALSE, true)
+}
+
+/// Barrel shifter for `a >> amount` shifting in `fill`: `Bdd::FALSE` for a
+/// logical shift, the sign bit for an arithmetic one.
+pub fn shift_right(m: &mut Manager, a: &[Bdd], amount: &[Bdd], fill: Bdd) -> Vec<Bdd> {
+    barrel(m, a, amount, fill, false)
+}
+
+/// Ripple-carry adder, the carry out of the top bit is dropped.
+pub fn add(m: &mut Manager, a: &[Bdd], b: &[Bdd]) -> Vec<Bdd> {
+    ripple(m, a, b, Bdd::FALSE).0
+}
+
+/// Subtractor built as `a + ~b + 1`.
+pub fn sub(m: &mut Manager, a: &[Bdd], b: &[Bdd]) -> Vec<Bdd> {
+    let not_b: Vec<Bdd> = b.iter().map(|x| !*x).collect();
+    ripple(m, a, &not_b, Bdd::TRUE).0
+}
+
+/// Array multiplier: one row of AND gates per bit of `b`, accumulated with
+/// ripple-carry adders and truncated to the operand width.
+pub fn mul(m: &mut Manager, a: &[Bdd], b: &[Bdd]) -> Vec<Bdd> {
+    let width = a.len();
+    let mut acc: Vec<Bdd> = vec![Bdd::FALSE; width];
+    for (shift, y) in b.iter().enumerate() {
+        let mut row: Vec<Bdd> = vec![Bdd::FALSE; width];
+        for i in shift..width {
+            row[i] = m.and(a[i - shift], *y);
+        }
+        acc = add(m, &acc, &row);
+    }
+    acc
+}
+
+/// Restoring divider returning `(quotient, remainder)`. Dividing by zero
+/// gives an all-ones quotient and `a` as the remainder, which is what the
+/// hardware computes; Verilog leaves that case undefined.
+pub fn div_rem(m: &mut Manager, a: &[Bdd], b: &[Bdd]) -> (Vec<Bdd>, Vec<Bdd>) {
+    let width = a.len();
+    let mut divisor: Vec<Bdd> = b.to_vec();
+    divisor.push(Bdd::FALSE);
+    let not_divisor: Vec<Bdd> = divisor.iter().map(|x| !*x).collect();
+
+    let mut remainder: Vec<Bdd> = vec![Bdd::FALSE; width];
+    let mut quotient: Vec<Bdd> = vec![Bdd::FALSE; width];
+    for i in (0..width).rev() {
+        /* shift the next dividend bit in, one extra bit keeps it exact */
+        let mut partial: Vec<Bdd> = vec![a[i]];
+        partial.extend_from_slice(&remainder);
+
+        let (difference, no_borrow) = ripple(m, &partial, &not_divisor, Bdd::TRUE);
+        quotient[i] = no_borrow;
+        remainder = (0..width)
+            .map(|j| m.ite(no_borrow, difference[j], partial[j]))
+            .collect();
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bdd::{Bdd, Manager};
+    use crate::bitblast::{add, div_rem, equal, less_than, mul, shift_left, shift_right, sub};
+
+    #[test]
+    fn test_arithmetic_exhaustive() {
+        let mut m = Manager::new();
+        let a: Vec<Bdd> = (0..4).map(|i| m.var(&format!("a[{}]", i))).collect();
+        let b: Vec<Bdd> = (0..4).map(|i| m.var(&format!("b[{}]", i))).collect();
+
+        let sum = add(&mut m, &a, &b);
+        let difference = sub(&mut m, &a, &b);
+        let product = mul(&mut m, &a, &b);
+        let (quotient, remainder) = div_rem(&mut m, &a, &b);
+
+        let value = |m: &Manager, bits: &[Bdd], assignment: &[bool]| -> u32 {
+            bits.iter()
+                .enumerate()
+                .map(|(i, f)| (m.eval(*f, assignment) as u32) << i)
+                .sum()
+        };
+
+        let eq = equal(&mut m, &a, &b);
+        let lt = less_than(&mut m, &a, &b);
+        let shl = shift_left(&mut m, &a, &b);
+        let shr = shift_right(&mut m, &a, &b, Bdd::FALSE);
+        let ashr = shift_right(&mut m, &a, &b, a[3]);
+
+        for x in 0..16u32 {
+            for y in 0..16u32 {
+                let assignment: Vec<bool> = (0..4)
+                    .map(|i| x >> i & 1 == 1)
+                    .chain((0..4).map(|i| y >> i & 1 == 1))
+                    .collect();
+                assert_eq!(value(&m, &sum, &assignment), (x + y) % 16);
+                assert_eq!(value(&m, &difference, &assignment), x.wrapping_sub(y) % 16);
+                assert_eq!(value(&m, &product, &assignment), (x * y) % 16);
+                assert_eq!(
+                    value(&m, &quotient, &assignment),
+                    x.checked_div(y).unwrap_or(15)
+                );
+                assert_eq!(
+                    value(&m, &remainder, &assignment),
+                    x.checked_rem(y).unwrap_or(x)
+                );
+                assert_eq!(m.eval(eq, &assignment), x == y);
+                assert_eq!(m.eval(lt, &assignment), x < y);
+                assert_eq!(value(&m, &shl, &assignment), (x << y) % 16);
+                assert_eq!(value(&m, &shr, &assignment), x >> y);
+                let signed = ((x as i32) << 28 >> 28) >> y.min(3);
+                assert_eq!(value(&m, &ashr, &assignment), signed as u32 % 16);
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::ast::Span;
+use lalrpop_util::ParseError;
+use std::fmt::Display;
+
+/// Error raised from a grammar action, e.g. a malformed number literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserError {
+    pub message: &'static str,
+    pub span: Span,
+}
+
+/// Error pointing at a span of the source, rendered rustc style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub expected: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str, span: Span) -> Self {
+        Diagnostic {
+            message: message.to_string(),
+            span,
+            expected: Vec::new(),
+        }
+    }
+
+    /// 1-based line and column of `offset` in `source`.
+    pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        (line, column)
+    }
+
+    /// Renders the message, its position, the offending source line with a
+    /// caret underline, and the expected tokens if there are any.
+    pub fn render(&self, source: &str) -> String {
+        let (line, column) = Diagnostic::line_column(source, self.span.start);
+        let text = source.lines().nth(line - 1).unwrap_or("");
+        let gutter = " ".repeat(line.to_string().len());
+
+        /* underline at least one column, and never past the end of the line */
+        let line_end = text.chars().count() + 1;
+        let width = source[self.span.start.min(source.len())..self.span.end.min(source.len())]
+            .chars()
+            .take_while(|c| *c != '\n')
+            .count()
+            .clamp(1, line_end + 1 - column);
+
+        let mut result = format!("error: {}\n", self.message);
+        result += &format!("{}--> {}:{}\n", gutter, line, column);
+        result += &format!("{} |\n", gutter);
+        result += &format!("{} | {}\n", line, text);
+        result += &format!(
+            "{} | {}{}\n",
+            gutter,
+            " ".repeat(column - 1),
+            "^".repeat(width)
+        );
+        if !self.expected.is_empty() {
+            result += &format!(
+                "{} = expected one of {}\n",
+                gutter,
+                self.expected.join(", ")
+            );
+        }
+        result
+    }
+}
+
+/* grammar terminals are quoted literals or raw regexes; name the regexes */
+fn describe_token(token: &str) -> String {
+    if !token.starts_with("r#") {
+        return token.to_string();
+    }
+    if token.contains("A-Za-z") || token.contains("!-~") {
+        String::from("identifier")
+    } else if token.contains("h|H") {
+        String::from("hex number")
+    } else if token.contains("b|B") {
+        String::from("base")
+    } else if token.contains("+|-") {
+        String::from("signed number")
+    } else {
+        String::from("number")
+    }
+}
+
+impl<T: Display> From<ParseError<usize, T, UserError>> for Diagnostic {
+    fn from(value: ParseError<usize, T, UserError>) -> Self {
+        let expected = |e: Vec<String>| -> Vec<String> {
+            let mut list: Vec<String> = Vec::new();
+            for t in e.iter().map(|t| describe_token(t)) {
+                if !list.contains(&t) {
+                    list.push(t);
+                }
+            }
+            list
+        };
+        match value {
+            ParseError::InvalidToken { location } => Diagnostic::new(
+                "invalid token",
+                Span {
+                    start: location,
+                    end: location + 1,
+                },
+            ),
+            ParseError::UnrecognizedEOF {
+                location,
+                expected: e,
+            } => Diagnostic {
+                message: String::from("unexpected end of input"),
+                span: Span {
+                    start: location,
+                    end: location,
+                },
+                expected: expected(e),
+            },
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected: e,
+            } => Diagnostic {
+                message: format!("unexpected token `{}`", token),
+                span: Span { start, end },
+                expected: expected(e),
+            },
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => Diagnostic::new(&format!("extra token `{}`", token), Span { start, end }),
+            ParseError::User { error } => Diagnostic::new(error.message, error.span),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::Diagnostic;
+    use crate::verilog;
+
+    fn render(source: &str) -> String {
+        let e = verilog::ExprParser::new().parse(source).unwrap_err();
+        Diagnostic::from(e).render(source)
+    }
+
+    #[test]
+    fn test_caret_diagnostics() {
+        let report = render("a & 1'b2");
+        assert_eq!(
+            report,
+            "error: invalid binary base number.\n --> 1:5\n  |\n1 | a & 1'b2\n  |     ^^^^\n"
+        );
+
+        let report = render("||a || |b");
+        assert!(report.starts_with("error: unexpected token `||`\n --> 1:1\n"));
+        assert!(report.contains("1 | ||a || |b\n  | ^^\n"));
+        assert!(report.contains("identifier"));
+
+        /* comments are skipped, the position counts the lines before them */
+        let report = render("a & // first\n/* second\n line */ b &\n  c ~ d");
+        assert!(report.starts_with("error: unexpected token `~`\n --> 4:5\n"));
+        assert!(report.contains("4 |   c ~ d\n  |     ^\n"));
+
+        let report = render("(a & b");
+        assert!(report.starts_with("error: unexpected end of input\n --> 1:7\n"));
+        assert!(report.contains("  |       ^\n"));
+    }
+}
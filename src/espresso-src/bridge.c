@@ -3,183 +3,366 @@
   Replaces main.c
  */
 #include "../espresso-src/espresso.h"
+#include "bridge.h"
+#include <limits.h>
+#include <setjmp.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
 
-char **get_solution(pPLA);
-void add_to_buffer(char **, unsigned int *, unsigned int *, char);
+/* pair.c leaves it out of espresso.h */
+extern int **find_pairing_cost(pPLA PLA, int strategy);
+/* cvrmisc.c: fatal() longjmps here instead of exiting when it is set */
+extern jmp_buf *fatal_trap;
+/* cvrin.c: product terms the last read_pla skipped as malformed */
+extern int lines_ignored;
 
-char **run_espresso(FILE *fpla, unsigned int *ret_count) {
-  pPLA PLA;
+/*
+  Minimizes PLA->F in place with the algorithm `mode` selects. On a
+  verification failure the minimized cover is not equivalent to the input
+  and the caller must not use it.
+ */
+static int minimize(pPLA PLA, int mode) {
   bool error;
   cost_t cost;
-  pcover fold;
-  char **result = NULL;
-
-  if (read_pla(fpla, TRUE, TRUE, FD_type, &PLA) == EOF) {
-    return NULL;
-  }
-
-  // makes sure free() won't crash on this variable
-  PLA->filename = NULL;
+  pcover fold, F;
 
   fold = sf_save(PLA->F);
-  PLA->F = espresso(PLA->F, PLA->D, PLA->R);
+  F = PLA->F;
+  switch (mode) {
+  case ESPRESSO_EXACT:
+    PLA->F = minimize_exact(F, PLA->D, PLA->R, TRUE);
+    free_cover(F); /* the exact minimizers leave the input cover alone */
+    break;
+  case ESPRESSO_EXACT_LITERALS:
+    PLA->F = minimize_exact_literals(F, PLA->D, PLA->R, TRUE);
+    free_cover(F);
+    break;
+  default:
+    PLA->F = espresso(F, PLA->D, PLA->R);
+    break;
+  }
   EXECUTE(error = verify(PLA->F, fold, PLA->D), VERIFY_TIME, PLA->F, cost);
+  free_cover(fold);
 
-  if (error) {
-    PLA->F = fold;
-    (void)check_consistency(PLA);
-  } else {
-    free_cover(fold);
-    *ret_count = PLA->F->count;
-    result = get_solution(PLA);
-  }
+  return error ? ESPRESSO_VERIFY_ERROR : ESPRESSO_OK;
+}
 
-  /* cleanup all used memory */
-  free_PLA(PLA);
+/* frees all global cube state, ready for the next call */
+static void reset(void) {
+  if (cube.fullset != NULL) {
+    setdown_cube(); /* free the cube/cdata structure data */
+  }
   FREE(cube.part_size);
-  setdown_cube(); /* free the cube/cdata structure data */
-  sf_cleanup();   /* free unused set structures */
-  sm_cleanup();   /* sparse matrix cleanup */
+  sf_cleanup(); /* free unused set structures */
+  sm_cleanup(); /* sparse matrix cleanup */
+}
 
-  return result;
+/* frees the PLA and all global cube state */
+static void cleanup(pPLA PLA) {
+  free_PLA(PLA);
+  reset();
 }
 
-char **run_espresso_from_data(char **data, unsigned int length,
-                              unsigned int *ret_count) {
-  if (length == 0) {
-    return NULL;
+/*
+  Picks the phase of every output the way phase_assignment() does without
+  the repeated strategy, then swaps the on-set and off-set of the outputs
+  realized in negative phase. Needs PLA->R.
+ */
+static void assign_phase(pPLA PLA) {
+  if (PLA->phase != NULL) {
+    set_free(PLA->phase);
   }
+  PLA->phase = find_phase(PLA, 0, (pcube)NULL);
+  (void)set_phase(PLA);
+}
 
-  for (int i = 0; i < length; i++) {
-    printf("%s\n", data[i]);
+/*
+  Pairs the binary inputs of a minimized PLA the way find_optimal_pairing()
+  does, estimating every pair by a full minimization, and minimizes again
+  over the paired variables. Only pairs that save cubes are kept. Needs
+  PLA->R.
+ */
+static int pair_inputs(pPLA PLA, int mode) {
+  int n = cube.num_binary_vars;
+  int first_output = cube.first_part[cube.output];
+  int **cost;
+  ppair best = NULL, pair;
+  pcube phase;
+
+  if (n < 2) {
+    return ESPRESSO_OK;
   }
 
-  FILE *tempPLA = tmpfile();
-
-  for (unsigned int i = 0; i < length; ++i) {
-    fputs(data[i], tempPLA);
-    fputc('\n', tempPLA);
+  cost = find_pairing_cost(PLA, 2);
+  if (n <= 14) {
+    best = pair_best_cost(cost);
+  } else {
+    (void)greedy_best_cost(cost, &best);
   }
-
-  rewind(tempPLA);
-
-  char **result = run_espresso(tempPLA, ret_count);
-  fclose(tempPLA);
-
-  return result;
-}
-
-char **run_espresso_from_path(char *path, unsigned int *ret_count) {
-  if (strlen(path) == 0) {
-    return NULL;
+  pair = pair_new(n);
+  for (int k = 0; best != NULL && k < best->cnt && pair->cnt < UCHAR_MAX;
+       k++) {
+    if (cost[best->var1[k] - 1][best->var2[k] - 1] > 0) {
+      pair->var1[pair->cnt] = best->var1[k];
+      pair->var2[pair->cnt] = best->var2[k];
+      pair->cnt++;
+    }
   }
+  if (best != NULL) {
+    pair_free(best);
+  }
+  for (int i = 0; i < n; i++) {
+    FREE(cost[i]);
+  }
+  FREE(cost);
 
-  FILE *fpla = fopen(path, "r");
-
-  if (fpla == NULL) {
-    fprintf(stderr, "[Espresso Logic Minimizer] Cannot open file %s\n", path);
-    return NULL;
+  if (pair->cnt == 0) {
+    pair_free(pair);
+    return ESPRESSO_OK;
   }
+  PLA->pair = pair;
+  set_pair1(PLA, FALSE);
+
+  /* carry the phase over to the new cube structure */
+  if (PLA->phase != NULL) {
+    phase = set_save(cube.fullset);
+    for (int i = 0; i < cube.part_size[cube.output]; i++) {
+      if (!is_in_set(PLA->phase, first_output + i)) {
+        set_remove(phase, cube.first_part[cube.output] + i);
+      }
+    }
+    set_free(PLA->phase);
+    PLA->phase = phase;
+  }
+  return minimize(PLA, mode);
+}
 
-  char **result = run_espresso(fpla, ret_count);
+/* the values a cube allows for pair k, bit 2a+b for the values (a, b) */
+static unsigned char get_pair(pcube p, int k) {
+  int first = cube.first_part[cube.num_binary_vars + k];
+  unsigned char values = 0;
 
-  fclose(fpla);
-  return result;
+  /* pairvar() puts (1, 1) first and (0, 0) last */
+  for (int v = 0; v < 4; v++) {
+    if (is_in_set(p, first + 3 - v)) {
+      values |= 1 << v;
+    }
+  }
+  return values;
 }
 
-char **get_solution(pPLA PLA) {
+/*
+  Copies PLA->F out as ESPRESSO_CUBE_WIDTH(inputs, outputs) bytes per cube,
+  in the encoding described in bridge.h, and the info row after them.
+ */
+static unsigned char *get_solution(pPLA PLA) {
   register pcube last, p;
-  char **solutions = malloc(((PLA->F)->count + 1) * sizeof(char *));
-  unsigned int current_solution = 0, buffer_size = 128;
-  char *buffer =
-      malloc(buffer_size); // dynamic alloc to allow realloc if necessary
-
-  if (solutions == NULL || buffer == NULL) {
-    fprintf(stderr,
-            "[espresso-logic-minimizer] FATAL: memory allocation failed\n");
-
-    if (solutions)
-      free(solutions);
-    if (buffer)
-      free(buffer);
+  int pairs = PLA->pair == NULL ? 0 : PLA->pair->cnt;
+  unsigned int inputs = cube.num_binary_vars + 2 * pairs;
+  unsigned int outputs = cube.part_size[cube.output];
+  unsigned int width = ESPRESSO_CUBE_WIDTH(inputs, outputs);
+  unsigned char *solution = malloc((PLA->F->count + 1) * width);
+  unsigned char *info, *cursor = solution;
+
+  if (solution == NULL) {
     return NULL;
   }
 
-  // we set the last item +1 to NULL to let caller functions know when to stop
-  // reading the array
-  solutions[(PLA->F)->count] = NULL;
+  info = solution + PLA->F->count * width;
+  memset(info, 0, inputs);
+  for (int k = 0; k < pairs; k++) {
+    info[PLA->pair->var1[k] - 1] = k + 1;
+    info[PLA->pair->var2[k] - 1] = k + 1;
+  }
+  for (unsigned int i = 0; i < outputs; i++) {
+    info[inputs + i] = PLA->phase == NULL ||
+                       is_in_set(PLA->phase, cube.first_part[cube.output] + i);
+  }
 
   foreach_set(PLA->F, last, p) {
-    unsigned int cursor = 0;
+    /* unpaired inputs keep their order in front of the pairs */
+    unsigned int binary = 0;
+    for (unsigned int var = 0; var < inputs; var++) {
+      if (info[var] == 0) {
+        *cursor++ = GETINPUT(p, binary);
+        binary++;
+      } else {
+        *cursor++ = get_pair(p, info[var] - 1);
+      }
+    }
+    for (unsigned int i = 0; i < outputs; i++) {
+      *cursor++ = GETOUTPUT(p, i);
+    }
+  }
+  return solution;
+}
 
-    /*
-      Rewrite of cvrout.c:print_cube to replace stdout printing with
-      variable storage
-     */
-    int i, var;
+/* minimizes a PLA whose cube structure and covers are set up */
+static int run_espresso(pPLA PLA, int mode, int flags,
+                        unsigned char **solution, unsigned int *ret_count) {
+  int status;
 
-    for (var = 0; var < cube.num_binary_vars; var++) {
-      add_to_buffer(&buffer, &buffer_size, &cursor, "?01-"[GETINPUT(p, var)]);
+  if (flags & ESPRESSO_PHASE) {
+    assign_phase(PLA);
+  }
+  status = minimize(PLA, mode);
+  if (status == ESPRESSO_OK && (flags & ESPRESSO_PAIR)) {
+    status = pair_inputs(PLA, mode);
+  }
+
+  if (status == ESPRESSO_OK) {
+    *solution = get_solution(PLA);
+    if (*solution == NULL) {
+      status = ESPRESSO_ALLOC_ERROR;
+    } else {
+      *ret_count = PLA->F->count;
     }
+  }
 
-    for (var = cube.num_binary_vars; var < cube.num_vars - 1; var++) {
-      add_to_buffer(&buffer, &buffer_size, &cursor, ' ');
+  cleanup(PLA);
+  return status;
+}
 
-      for (i = cube.first_part[var]; i <= cube.last_part[var]; i++) {
-        add_to_buffer(&buffer, &buffer_size, &cursor,
-                      "01"[is_in_set(p, i) != 0]);
+/* appends `count` cubes in the encoding of bridge.h to *cover */
+static int add_cubes(pcover *cover, const unsigned char *cubes,
+                     unsigned int count, unsigned int inputs,
+                     unsigned int outputs) {
+  pcube cf = cube.temp[0];
+  const unsigned char *row;
+
+  for (unsigned int c = 0; c < count; c++) {
+    bool save = FALSE;
+
+    row = cubes + c * ESPRESSO_CUBE_WIDTH(inputs, outputs);
+    set_clear(cf, cube.size);
+    for (unsigned int var = 0; var < inputs; var++) {
+      if (row[var] == 0 || row[var] > ESPRESSO_DONT_CARE) {
+        return ESPRESSO_READ_ERROR;
+      }
+      if (row[var] & ESPRESSO_ZERO) {
+        set_insert(cf, 2 * var);
+      }
+      if (row[var] & ESPRESSO_ONE) {
+        set_insert(cf, 2 * var + 1);
       }
     }
-
-    if (cube.output != -1) {
-      int last_part = cube.last_part[cube.output];
-      add_to_buffer(&buffer, &buffer_size, &cursor, ' ');
-
-      for (i = cube.first_part[cube.output]; i <= last_part; i++) {
-        add_to_buffer(&buffer, &buffer_size, &cursor,
-                      "01"[is_in_set(p, i) != 0]);
+    for (unsigned int i = 0; i < outputs; i++) {
+      if (row[inputs + i]) {
+        set_insert(cf, cube.first_part[cube.output] + i);
+        save = TRUE;
       }
     }
-
-    solutions[current_solution] = malloc(cursor + 1);
-
-    if (solutions[current_solution] == NULL) {
-      fprintf(stderr,
-              "[espresso-logic-minimizer] FATAL: memory allocation failed\n");
-
-      for (unsigned int j = 0; j < current_solution; j++)
-        free(solutions[j]);
-      free(buffer);
-      return NULL;
+    if (save) {
+      *cover = sf_addset(*cover, cf);
     }
+  }
+  return ESPRESSO_OK;
+}
 
-    buffer[cursor] = '\0';
-    strcpy(solutions[current_solution], buffer);
-    current_solution++;
+int run_espresso_from_cubes(unsigned int inputs, unsigned int outputs,
+                            const unsigned char *on_cubes,
+                            unsigned int on_count,
+                            const unsigned char *dc_cubes,
+                            unsigned int dc_count, int mode, int flags,
+                            unsigned char **solution,
+                            unsigned int *ret_count) {
+  pPLA PLA;
+  int status;
+  jmp_buf trap;
+
+  *solution = NULL;
+  *ret_count = 0;
+  if (outputs == 0) {
+    return ESPRESSO_EMPTY_INPUT;
   }
 
-  free(buffer);
-  return solutions;
+  /* a fatal() error leaks the PLA, which may be half torn down */
+  if (setjmp(trap) != 0) {
+    fatal_trap = NULL;
+    reset();
+    return ESPRESSO_READ_ERROR;
+  }
+  fatal_trap = &trap;
+
+  /* the same sizes ".i inputs" and ".o outputs" give */
+  cube.num_binary_vars = inputs;
+  cube.num_vars = inputs + 1;
+  cube.part_size = ALLOC(int, cube.num_vars);
+  cube.part_size[cube.num_vars - 1] = outputs;
+  cube_setup();
+
+  PLA = new_PLA();
+  PLA->pla_type = FD_type;
+  PLA->F = new_cover(on_count);
+  PLA->D = new_cover(dc_count);
+
+  status = add_cubes(&PLA->F, on_cubes, on_count, inputs, outputs);
+  if (status == ESPRESSO_OK) {
+    status = add_cubes(&PLA->D, dc_cubes, dc_count, inputs, outputs);
+  }
+  if (status == ESPRESSO_OK) {
+    /* the off-set is whatever is neither on nor don't-care */
+    PLA->R = complement(cube2list(PLA->F, PLA->D));
+    status = run_espresso(PLA, mode, flags, solution, ret_count);
+  } else {
+    cleanup(PLA);
+  }
+  fatal_trap = NULL;
+  return status;
 }
 
-void add_to_buffer(char **buffer, unsigned int *buffer_size,
-                   unsigned int *cursor, char chr) {
-  if (*cursor + 1 > *buffer_size) {
-    char *new_buffer = realloc(*buffer, *buffer_size * 2);
+int run_espresso_from_path(char *path, int mode, int flags,
+                           unsigned int *inputs, unsigned int *outputs,
+                           unsigned char **solution, unsigned int *ret_count) {
+  pPLA PLA;
+  FILE *volatile fpla; /* read after a longjmp */
+  int read, status;
+  jmp_buf trap;
 
-    if (new_buffer == NULL) {
-      fprintf(stderr,
-              "[espresso-logic-minimizer] FATAL: memory allocation failed\n");
-      return;
+  *solution = NULL;
+  *ret_count = 0;
+  if (strlen(path) == 0) {
+    return ESPRESSO_EMPTY_INPUT;
+  }
+
+  fpla = fopen(path, "r");
+  if (fpla == NULL) {
+    return ESPRESSO_IO_ERROR;
+  }
+
+  /* a malformed PLA, such as an ON-set that meets the OFF-set of a
+   * .type fr file, ends in fatal(); the PLA is leaked as above */
+  if (setjmp(trap) != 0) {
+    fatal_trap = NULL;
+    if (fpla != NULL) {
+      fclose(fpla);
     }
+    reset();
+    return ESPRESSO_READ_ERROR;
+  }
+  fatal_trap = &trap;
 
-    *buffer_size *= 2;
-    *buffer = new_buffer;
+  read = read_pla(fpla, TRUE, TRUE, FD_type, &PLA);
+  fclose(fpla);
+  fpla = NULL;
+
+  /* only binary-valued inputs with one output part map onto cubes, and
+   * a product term Espresso could not read is not silently dropped */
+  if (read == EOF || lines_ignored > 0 || cube.output == -1 ||
+      cube.num_binary_vars != cube.num_vars - 1) {
+    cleanup(PLA);
+    fatal_trap = NULL;
+    return ESPRESSO_READ_ERROR;
   }
 
-  (*buffer)[*cursor] = chr;
-  (*cursor)++;
+  // makes sure free() won't crash on this variable
+  PLA->filename = NULL;
+  *inputs = cube.num_binary_vars;
+  *outputs = cube.part_size[cube.output];
+  status = run_espresso(PLA, mode, flags, solution, ret_count);
+  fatal_trap = NULL;
+  return status;
 }
+
+void free_solution(unsigned char *solution) { free(solution); }
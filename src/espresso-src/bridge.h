@@ -3,8 +3,57 @@
 
 // extern void init_runtime(void);
 
-char **run_espresso_from_data(char **data, unsigned int length,
-                              unsigned int *ret_count);
-char **run_espresso_from_path(char *path, unsigned int *ret_count);
+/* status codes returned by the entry points, mirrored in espresso.rs */
+#define ESPRESSO_OK 0
+#define ESPRESSO_EMPTY_INPUT 1
+#define ESPRESSO_IO_ERROR 2
+#define ESPRESSO_READ_ERROR 3
+#define ESPRESSO_VERIFY_ERROR 4
+#define ESPRESSO_ALLOC_ERROR 5
+
+/* minimization algorithms, mirrored in espresso.rs */
+#define ESPRESSO_HEURISTIC 0
+#define ESPRESSO_EXACT 1
+#define ESPRESSO_EXACT_LITERALS 2
+
+/* optional steps, ORed into the flags of the entry points */
+#define ESPRESSO_PHASE 1
+#define ESPRESSO_PAIR 2
+
+/*
+  Cubes are passed as one byte per input followed by one byte per output.
+  An input byte is the set of values the input may take, as in Espresso's
+  positional notation: ESPRESSO_ZERO, ESPRESSO_ONE or both. An output byte
+  is 1 when the cube belongs to that output and 0 otherwise.
+  In a solution the two inputs a and b of a pair both hold the set of
+  values the pair may take instead, bit 2a+b for the values (a, b).
+ */
+#define ESPRESSO_ZERO 1
+#define ESPRESSO_ONE 2
+#define ESPRESSO_DONT_CARE 3
+#define ESPRESSO_CUBE_WIDTH(inputs, outputs) ((inputs) + (outputs))
+
+/* Minimizes the on-set cubes, free to cover any of the don't-care cubes.
+ * On ESPRESSO_OK *solution holds *ret_count cubes, released with
+ * free_solution. On any other status *solution is NULL.
+ * With ESPRESSO_PHASE Espresso first chooses, per output, whether to
+ * realize the function or its complement (opo.c). The cubes of an output in
+ * negative phase cover its off-set. With ESPRESSO_PAIR it then decodes
+ * pairs of inputs together where that saves cubes (pair.c).
+ * One more row follows the cubes. Its input bytes number the pair of each
+ * input from 1, 0 for an unpaired input, and its output bytes are 1 for an
+ * output in positive phase and 0 otherwise. */
+int run_espresso_from_cubes(unsigned int inputs, unsigned int outputs,
+                            const unsigned char *on_cubes,
+                            unsigned int on_count,
+                            const unsigned char *dc_cubes,
+                            unsigned int dc_count, int mode, int flags,
+                            unsigned char **solution,
+                            unsigned int *ret_count);
+/* Reads a PLA file with binary inputs and reports its sizes as well. */
+int run_espresso_from_path(char *path, int mode, int flags,
+                           unsigned int *inputs, unsigned int *outputs,
+                           unsigned char **solution, unsigned int *ret_count);
+void free_solution(unsigned char *solution);
 
 #endif
@@ -8,6 +8,8 @@
 
 static bool line_length_error;
 static int lineno;
+/* product terms parse_pla skipped as malformed */
+int lines_ignored;
 
 void skip_line(register FILE *fpin, register FILE *fpout, register int echo)
 {
@@ -115,7 +117,7 @@ void read_cube(register FILE *fp, pPLA PLA) {
 
         if (i > last) {
           fprintf(stderr, "declared size of variable %d (counting from variable 0) is too small\n", var);
-          exit(-1);
+          fatal("error reading .mv");
         }
       }
     } else {
@@ -201,7 +203,8 @@ void read_cube(register FILE *fp, pPLA PLA) {
 
   bad_char:
     fprintf(stderr, "(warning): input line #%d ignored\n", lineno);
-    skip_line(fp, stdout, TRUE);
+    lines_ignored++;
+    skip_line(fp, stderr, TRUE);
     return;
 }
 
@@ -211,6 +214,7 @@ void parse_pla(FILE *fp, pPLA PLA) {
 
   lineno = 1;
   line_length_error = FALSE;
+  lines_ignored = 0;
 
 loop:
   switch(ch = getc(fp)) {
@@ -1,4 +1,5 @@
 #include "espresso.h"
+#include <setjmp.h>
 
 
 /* cost -- compute the cost of a cover */
@@ -110,9 +111,15 @@ void totals(long int time, int i, pset_family T, pcost cost)
 }
 
 
+/* where fatal() returns to instead of exiting, NULL when it should exit */
+jmp_buf *fatal_trap = NULL;
+
 /* fatal -- report fatal error message and take a dive */
 void fatal(char *s)
 {
     fprintf(stderr, "espresso: %s\n", s);
+    if (fatal_trap != NULL) {
+	longjmp(*fatal_trap, 1);
+    }
     exit(1);
 }
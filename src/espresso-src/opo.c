@@ -176,6 +176,10 @@ pcover opo(pset phase, pset_family T, pset_family D, pset_family R, int first_ou
 
     /* largest set is on top -- select primes which are inferred from it */
     pdest = temp->data;
+    /* a single output skips unate_intersect, which is what sorts them */
+    if (temp->count == 2 && set_ord(GETSET(temp, 1)) > set_ord(pdest)) {
+	pdest = GETSET(temp, 1);
+    }
     T1 = new_cover(T->count);
     foreachi_set(T, i, p) {
 	if (! is_in_set(pdest, i)) {
@@ -1,50 +1,659 @@
-use std::ffi::CStr;
+//! Safe wrapper around the vendored Espresso minimizer.
+//!
+//! Espresso keeps its state in C globals: the `cube` and `cdata` structures
+//! sized by each PLA, the option flags of `globals.c`, and the free lists
+//! behind `sf_cleanup` and `sm_cleanup`. A run sets all of it up and tears
+//! it down again, so runs cannot overlap. Every entry point of this module
+//! takes the process-wide `ESPRESSO` lock for the whole run, which makes the
+//! functions safe to call from any number of threads; concurrent callers
+//! are served one at a time. Nothing else may call into the bridge.
+//!
+//! Every failure of a run comes back as an `EspressoError`. Espresso
+//! reports a malformed PLA through `fatal()`, which the bridge traps with
+//! `setjmp` instead of letting it exit. The exception is running out of
+//! memory inside Espresso's own routines, which still ends the process,
+//! as it does for a failed Rust allocation.
+
 use std::ffi::CString;
-use std::ffi::{c_char, c_uint};
+use std::ffi::{c_char, c_int, c_uchar, c_uint};
+use std::fmt::Display;
+use std::sync::{Mutex, MutexGuard};
+
+/* status codes of bridge.h */
+const ESPRESSO_OK: c_int = 0;
+const ESPRESSO_EMPTY_INPUT: c_int = 1;
+const ESPRESSO_IO_ERROR: c_int = 2;
+const ESPRESSO_READ_ERROR: c_int = 3;
+const ESPRESSO_VERIFY_ERROR: c_int = 4;
+const ESPRESSO_ALLOC_ERROR: c_int = 5;
+
+/* minimization algorithms of bridge.h */
+const ESPRESSO_HEURISTIC: c_int = 0;
+const ESPRESSO_EXACT: c_int = 1;
+const ESPRESSO_EXACT_LITERALS: c_int = 2;
+
+/* optional steps of bridge.h */
+const ESPRESSO_PHASE: c_int = 1;
+const ESPRESSO_PAIR: c_int = 2;
+
+/* input encoding of bridge.h: the set of values an input may take */
+const ESPRESSO_ZERO: c_uchar = 1;
+const ESPRESSO_ONE: c_uchar = 2;
+const ESPRESSO_DONT_CARE: c_uchar = 3;
+
+/* serializes every run, the guarded state lives on the C side */
+static ESPRESSO: Mutex<()> = Mutex::new(());
+
+/* a panic cannot leave a run half done, the bridge cleans up before
+ * returning, so a poisoned lock is still good to use */
+fn lock() -> MutexGuard<'static, ()> {
+    ESPRESSO.lock().unwrap_or_else(|e| e.into_inner())
+}
 
 #[link(name = "espresso", kind = "static")]
 extern "C" {
-    pub fn run_espresso_from_data(
-        data: *const *const c_char,
-        length: c_uint,
+    fn run_espresso_from_cubes(
+        inputs: c_uint,
+        outputs: c_uint,
+        on_cubes: *const c_uchar,
+        on_count: c_uint,
+        dc_cubes: *const c_uchar,
+        dc_count: c_uint,
+        mode: c_int,
+        flags: c_int,
+        solution: *mut *mut c_uchar,
         ret_length: *mut c_uint,
-    ) -> *mut *const c_char;
-    pub fn run_espresso_from_path(
+    ) -> c_int;
+    fn run_espresso_from_path(
         path: *const c_char,
+        mode: c_int,
+        flags: c_int,
+        inputs: *mut c_uint,
+        outputs: *mut c_uint,
+        solution: *mut *mut c_uchar,
         ret_length: *mut c_uint,
-    ) -> *mut *const c_char;
+    ) -> c_int;
+    fn free_solution(solution: *mut c_uchar);
+}
+
+/// Why a call into Espresso produced no cover.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EspressoError {
+    /// The PLA has no outputs, or the path is empty.
+    EmptyInput,
+    /// The PLA file could not be opened.
+    Io,
+    /// The PLA could not be read, or holds something other than binary cubes.
+    Read,
+    /// The minimized cover failed `verify()` against the original one.
+    Verify,
+    /// Espresso ran out of memory while copying the solution out.
+    OutOfMemory,
+}
+
+impl Display for EspressoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            EspressoError::EmptyInput => "espresso was given an empty PLA",
+            EspressoError::Io => "espresso could not open its input",
+            EspressoError::Read => "espresso could not read the PLA",
+            EspressoError::Verify => "espresso produced a cover that fails verification",
+            EspressoError::OutOfMemory => "espresso ran out of memory",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for EspressoError {}
+
+/// Algorithm Espresso minimizes with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// The expand, reduce and irredundant loop of `espresso()`. Fast, but
+    /// the cover is not guaranteed to be minimum.
+    #[default]
+    Heuristic,
+    /// All primes and an exact covering, a minimum number of cubes.
+    Exact,
+    /// Like `Exact`, with primes weighted by their literal count.
+    ExactLiterals,
+}
+
+impl Mode {
+    fn code(self) -> c_int {
+        match self {
+            Mode::Heuristic => ESPRESSO_HEURISTIC,
+            Mode::Exact => ESPRESSO_EXACT,
+            Mode::ExactLiterals => ESPRESSO_EXACT_LITERALS,
+        }
+    }
+}
+
+/// Settings of one minimizer run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    pub mode: Mode,
+    /// Let Espresso realize an output as its complement when that takes
+    /// fewer cubes, see `Cover::phases`.
+    pub phase: bool,
+    /// Decode pairs of inputs together where that saves cubes, see
+    /// `Cover::pairs`.
+    pub pair: bool,
+}
+
+impl Options {
+    fn flags(&self) -> c_int {
+        let mut flags = 0;
+        if self.phase {
+            flags |= ESPRESSO_PHASE;
+        }
+        if self.pair {
+            flags |= ESPRESSO_PAIR;
+        }
+        flags
+    }
+}
+
+/// One product term: the literal of every input, `None` where the input is
+/// absent or paired, and the outputs the term belongs to. `pairs[k]` is the
+/// set of values pair k of the cover may take, bit `2a+b` for `(a, b)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cube {
+    pub inputs: Vec<Option<bool>>,
+    pub outputs: Vec<bool>,
+    pub pairs: Vec<u8>,
+}
+
+/// Prints the cube as a PLA row, e.g. `1-0 10`, with the values of every
+/// pair in between, `(0,0)` first: `1-- 0110 10`.
+impl Display for Cube {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inputs: String = self
+            .inputs
+            .iter()
+            .map(|l| match l {
+                Some(false) => '0',
+                Some(true) => '1',
+                None => '-',
+            })
+            .collect();
+        let outputs: String = self
+            .outputs
+            .iter()
+            .map(|o| if *o { '1' } else { '0' })
+            .collect();
+        write!(f, "{}", inputs)?;
+        for values in self.pairs.iter() {
+            let values: String = (0..4)
+                .map(|v| if values & (1 << v) != 0 { '1' } else { '0' })
+                .collect();
+            write!(f, " {}", values)?;
+        }
+        write!(f, " {}", outputs)
+    }
+}
+
+/// Multi-output sum of products over `inputs` inputs and `outputs` outputs.
+/// Where `phases[i]` is false the cubes of output i cover its complement,
+/// and `pairs` lists the inputs `(a, b)` decoded together, `a < b`; only a
+/// minimizer run with `Options::phase` or `Options::pair` produces these.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cover {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub cubes: Vec<Cube>,
+    pub phases: Vec<bool>,
+    pub pairs: Vec<(usize, usize)>,
+}
+
+impl Cover {
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        Cover {
+            inputs,
+            outputs,
+            cubes: Vec::new(),
+            phases: vec![true; outputs],
+            pairs: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cubes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cubes.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Cube> {
+        self.cubes.iter()
+    }
+
+    /// The cover as the lines of a PLA file, `.i` and `.o` through `.e`.
+    /// A negative phase is noted in a `#.phase` comment, as Espresso does,
+    /// and the pairs in a `#.pair` comment numbering the inputs from 1.
+    pub fn to_pla(&self) -> Vec<String> {
+        let mut ret: Vec<String> = Vec::new();
+        ret.push(format!(".i {}", self.inputs));
+        ret.push(format!(".o {}", self.outputs));
+        if self.phases.contains(&false) {
+            let phases: String = self
+                .phases
+                .iter()
+                .map(|p| if *p { '1' } else { '0' })
+                .collect();
+            ret.push(format!("#.phase {}", phases));
+        }
+        if !self.pairs.is_empty() {
+            let pairs: Vec<String> = self
+                .pairs
+                .iter()
+                .map(|(a, b)| format!("({} {})", a + 1, b + 1))
+                .collect();
+            ret.push(format!("#.pair {} {}", pairs.len(), pairs.join(" ")));
+        }
+        ret.extend(self.cubes.iter().map(|c| c.to_string()));
+        ret.push(String::from(".e"));
+        ret
+    }
+
+    /// `to_pla` with the names of the inputs and outputs as `.ilb` and
+    /// `.ob`. The cubes of `dc_set` follow as don't-care rows, `-` in the
+    /// outputs they belong to, under `.type fd`.
+    pub fn to_labeled_pla(
+        &self,
+        dc_set: &Cover,
+        inputs: &[String],
+        outputs: &[String],
+    ) -> Vec<String> {
+        let labels = |names: &[String]| -> String {
+            /* an escaped identifier ends in a space */
+            let names: Vec<&str> = names.iter().map(|n| n.trim_end()).collect();
+            names.join(" ")
+        };
+        let mut ret = self.to_pla();
+        let end = ret.pop().unwrap();
+        ret.insert(2, format!(".ilb {}", labels(inputs)));
+        ret.insert(3, format!(".ob {}", labels(outputs)));
+        if !dc_set.is_empty() {
+            ret.insert(4, String::from(".type fd"));
+            ret.extend(dc_set.iter().map(|c| {
+                let row = c.to_string();
+                let (inputs, outputs) = row.rsplit_once(' ').unwrap();
+                let outputs: String = outputs
+                    .chars()
+                    .map(|o| if o == '1' { '-' } else { o })
+                    .collect();
+                format!("{} {}", inputs, outputs)
+            }));
+        }
+        ret.push(end);
+        ret
+    }
+
+    /* the flat byte layout of bridge.h */
+    fn encode(&self) -> Vec<c_uchar> {
+        let mut data: Vec<c_uchar> = Vec::with_capacity(self.len() * (self.inputs + self.outputs));
+        for c in self.cubes.iter() {
+            data.extend(c.inputs.iter().map(|l| match l {
+                Some(false) => ESPRESSO_ZERO,
+                Some(true) => ESPRESSO_ONE,
+                None => ESPRESSO_DONT_CARE,
+            }));
+            data.extend(c.outputs.iter().map(|o| *o as c_uchar));
+        }
+        data
+    }
 }
 
-pub fn espresso_minimizer(data: Vec<String>) -> Vec<String> {
-    let mut cstrs: Vec<CString> = Vec::new();
-    let mut input: Vec<*const c_char> = Vec::new();
+/* copies the cubes out of a solution of the bridge and releases it */
+fn take_solution(
+    status: c_int,
+    inputs: usize,
+    outputs: usize,
+    solution: *mut c_uchar,
+    length: c_uint,
+) -> Result<Cover, EspressoError> {
+    let error = match status {
+        ESPRESSO_OK => None,
+        ESPRESSO_EMPTY_INPUT => Some(EspressoError::EmptyInput),
+        ESPRESSO_IO_ERROR => Some(EspressoError::Io),
+        ESPRESSO_READ_ERROR => Some(EspressoError::Read),
+        ESPRESSO_VERIFY_ERROR => Some(EspressoError::Verify),
+        ESPRESSO_ALLOC_ERROR => Some(EspressoError::OutOfMemory),
+        status => unreachable!("espresso bridge returned unknown status {}", status),
+    };
+    if let Some(e) = error {
+        return Err(e);
+    }
 
-    for d in data.iter() {
-        let mut new_data = d.clone();
-        new_data.retain(|c| c != '\0');
-        cstrs.push(CString::new(new_data.as_bytes()).unwrap());
+    let width = inputs + outputs;
+    /* SAFETY: on success the bridge returns `length` cubes of `width` bytes
+     * and the info row */
+    let data: &[c_uchar] =
+        unsafe { std::slice::from_raw_parts(solution, (length as usize + 1) * width) };
+    let mut cover = Cover::new(inputs, outputs);
+    let (cubes, info) = data.split_at(length as usize * width);
+    cover.phases = info[inputs..].iter().map(|p| *p != 0).collect();
+    /* pair k is numbered k + 1 on both of its inputs */
+    for k in 1..=*info[..inputs].iter().max().unwrap_or(&0) {
+        let mut members = (0..inputs).filter(|i| info[*i] == k);
+        if let (Some(a), Some(b)) = (members.next(), members.next()) {
+            cover.pairs.push((a, b));
+        }
+    }
+    for row in cubes.chunks(width.max(1)) {
+        cover.cubes.push(Cube {
+            inputs: row[..inputs]
+                .iter()
+                .zip(info.iter())
+                .map(|(l, pair)| match (*l, *pair) {
+                    (ESPRESSO_ZERO, 0) => Some(false),
+                    (ESPRESSO_ONE, 0) => Some(true),
+                    _ => None,
+                })
+                .collect(),
+            outputs: row[inputs..].iter().map(|o| *o != 0).collect(),
+            pairs: cover.pairs.iter().map(|(a, _)| row[*a]).collect(),
+        });
     }
+    /* SAFETY: the buffer came from the bridge's malloc */
+    unsafe { free_solution(solution) };
+    Ok(cover)
+}
 
-    for d in cstrs.iter() {
-        input.push(d.as_ptr());
+/// Minimizes the on-set given as a cover, without any text in between.
+/// The result may also cover any part of `dc_set`, which must have the
+/// same inputs and outputs. Both are read in positive phase and unpaired.
+pub fn espresso_minimizer(
+    on_set: &Cover,
+    dc_set: &Cover,
+    options: &Options,
+) -> Result<Cover, EspressoError> {
+    assert_eq!(
+        (on_set.inputs, on_set.outputs),
+        (dc_set.inputs, dc_set.outputs)
+    );
+    if on_set.outputs == 0 {
+        return Err(EspressoError::EmptyInput);
     }
+    if on_set.iter().all(|c| c.outputs.iter().all(|o| !o)) {
+        /* nothing to minimize, and Espresso wants at least one cube */
+        return Ok(Cover::new(on_set.inputs, on_set.outputs));
+    }
+
+    let on_data = on_set.encode();
+    let dc_data = dc_set.encode();
+    let mut solution: *mut c_uchar = std::ptr::null_mut();
+    let mut length: c_uint = 0;
+    let _guard = lock();
+    let status = unsafe {
+        run_espresso_from_cubes(
+            on_set.inputs as c_uint,
+            on_set.outputs as c_uint,
+            on_data.as_ptr(),
+            on_set.len() as c_uint,
+            dc_data.as_ptr(),
+            dc_set.len() as c_uint,
+            options.mode.code(),
+            options.flags(),
+            &mut solution,
+            &mut length,
+        )
+    };
+    take_solution(status, on_set.inputs, on_set.outputs, solution, length)
+}
+
+/// Minimizes the PLA file at `path`, which must have binary inputs only.
+pub fn espresso_minimizer_from_path(path: &str, options: &Options) -> Result<Cover, EspressoError> {
+    let path = CString::new(path).map_err(|_| EspressoError::Io)?;
+    let mut inputs: c_uint = 0;
+    let mut outputs: c_uint = 0;
+    let mut solution: *mut c_uchar = std::ptr::null_mut();
+    let mut length: c_uint = 0;
+    let _guard = lock();
+    let status = unsafe {
+        run_espresso_from_path(
+            path.as_ptr(),
+            options.mode.code(),
+            options.flags(),
+            &mut inputs,
+            &mut outputs,
+            &mut solution,
+            &mut length,
+        )
+    };
+    take_solution(status, inputs as usize, outputs as usize, solution, length)
+}
 
-    let mut result_length: c_uint = 0;
-    let result: *mut *const c_char = unsafe {
-        run_espresso_from_data(input.as_ptr(), input.len() as c_uint, &mut result_length)
+#[cfg(test)]
+mod tests {
+    use crate::espresso::{
+        espresso_minimizer, espresso_minimizer_from_path, Cover, Cube, EspressoError, Mode, Options,
     };
+    use std::thread;
+
+    fn cover(inputs: usize, rows: &[&str]) -> Cover {
+        let mut cover = Cover::new(inputs, rows[0].len() - inputs - 1);
+        for row in rows.iter() {
+            let (i, o) = row.split_once(' ').unwrap();
+            cover.cubes.push(Cube {
+                inputs: i
+                    .chars()
+                    .map(|c| match c {
+                        '0' => Some(false),
+                        '1' => Some(true),
+                        _ => None,
+                    })
+                    .collect(),
+                outputs: o.chars().map(|c| c == '1').collect(),
+                pairs: Vec::new(),
+            });
+        }
+        cover
+    }
+
+    fn minimize(on_set: &Cover, options: &Options) -> Result<Cover, EspressoError> {
+        espresso_minimizer(on_set, &Cover::new(on_set.inputs, on_set.outputs), options)
+    }
 
-    let ret: Vec<*const c_char> =
-        unsafe { Vec::from_raw_parts(result, result_length as usize, result_length as usize) };
+    #[test]
+    fn test_minimizer() {
+        /* a&b | a&~b is a */
+        let result = minimize(&cover(2, &["11 1", "10 1"]), &Options::default()).unwrap();
+        assert_eq!(result.to_pla(), [".i 2", ".o 1", "1- 1", ".e"]);
 
-    let mut ret_string: Vec<String> = Vec::new();
-    for d in ret.iter() {
-        unsafe {
-            let c: &CStr = CStr::from_ptr(*d);
-            ret_string.push(String::from(c.to_str().unwrap()));
+        let result =
+            minimize(&cover(2, &["1- 10", "0- 10", "11 01"]), &Options::default()).unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().any(|c| c.to_string() == "-- 10"));
+
+        let result = minimize(&cover(2, &["11 00"]), &Options::default()).unwrap();
+        assert!(result.is_empty());
+
+        assert_eq!(
+            minimize(&Cover::new(2, 0), &Options::default()),
+            Err(EspressoError::EmptyInput)
+        );
+        assert_eq!(
+            espresso_minimizer_from_path("/nonexistent/input.pla", &Options::default()),
+            Err(EspressoError::Io)
+        );
+
+        let path = std::env::temp_dir().join("espresso_minimizer_test.pla");
+        std::fs::write(&path, ".i 2\n.o 1\n.ilb a b\n.ob f\n01 1\n11 1\n.e\n").unwrap();
+        let result =
+            espresso_minimizer_from_path(path.to_str().unwrap(), &Options::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.to_pla(), [".i 2", ".o 1", "-1 1", ".e"]);
+    }
+
+    #[test]
+    fn test_malformed_pla() {
+        /* overlapping ON- and OFF-sets, a bad product term, a bad header */
+        for (k, text) in [
+            ".i 2\n.o 1\n.type fr\n1- 1\n11 0\n.e\n",
+            ".i 2\n.o 1\n1x 1\n01 1\n.e\n",
+            ".i two\n.o 1\n11 1\n.e\n",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let path = std::env::temp_dir().join(format!("espresso_malformed_test{}.pla", k));
+            std::fs::write(&path, text).unwrap();
+            let result = espresso_minimizer_from_path(path.to_str().unwrap(), &Options::default());
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(result, Err(EspressoError::Read));
+        }
+
+        /* Espresso is left ready for the next run */
+        let result = minimize(&cover(2, &["11 1", "10 1"]), &Options::default()).unwrap();
+        assert_eq!(result.to_pla(), [".i 2", ".o 1", "1- 1", ".e"]);
+    }
+
+    #[test]
+    fn test_labeled_pla() {
+        let on_set = cover(2, &["11 10", "01 01"]);
+        let dc_set = cover(2, &["10 10"]);
+        let names = |n: &[&str]| -> Vec<String> { n.iter().map(|s| s.to_string()).collect() };
+        let pla = on_set.to_labeled_pla(&dc_set, &names(&["a", "\\b "]), &names(&["y", "z"]));
+        assert_eq!(
+            pla,
+            [
+                ".i 2",
+                ".o 2",
+                ".ilb a \\b",
+                ".ob y z",
+                ".type fd",
+                "11 10",
+                "01 01",
+                "10 -0",
+                ".e"
+            ]
+        );
+
+        /* Espresso reads it back, free to merge 11 and 10 into 1- */
+        let path = std::env::temp_dir().join("espresso_labeled_test.pla");
+        std::fs::write(&path, pla.join("\n")).unwrap();
+        let result =
+            espresso_minimizer_from_path(path.to_str().unwrap(), &Options::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.to_pla(), [".i 2", ".o 2", "1- 10", "01 01", ".e"]);
+    }
+
+    #[test]
+    fn test_dont_cares() {
+        /* a&b with a&~b free to be anything becomes a */
+        let on_set = cover(2, &["11 1"]);
+        let result = minimize(&on_set, &Options::default()).unwrap();
+        assert_eq!(result.to_pla()[2], "11 1");
+        let dc_set = cover(2, &["10 1"]);
+        let result = espresso_minimizer(&on_set, &dc_set, &Options::default()).unwrap();
+        assert_eq!(result.to_pla()[2], "1- 1");
+    }
+
+    #[test]
+    fn test_exact_modes() {
+        /* a cyclic on-set, every minterm is covered by two primes */
+        let on_set = cover(3, &["000 1", "001 1", "010 1", "101 1", "110 1", "111 1"]);
+        for mode in [Mode::Heuristic, Mode::Exact, Mode::ExactLiterals] {
+            let options = Options {
+                mode,
+                ..Options::default()
+            };
+            let result = minimize(&on_set, &options).unwrap();
+            assert!(result.len() >= 3);
+            if mode != Mode::Heuristic {
+                assert_eq!(result.len(), 3);
+            }
+            let literals = result
+                .iter()
+                .flat_map(|c| c.inputs.iter().flatten())
+                .count();
+            assert!(mode != Mode::ExactLiterals || literals == 6);
         }
     }
 
-    println!("{:?}", result_length);
-    ret_string
+    #[test]
+    fn test_phase_assignment() {
+        /* a|b|c takes three cubes, its complement ~a&~b&~c only one; the
+         * second output a&b is cheaper as it is */
+        let on_set = cover(3, &["1-- 10", "-1- 10", "--1 10", "11- 01"]);
+        let result = minimize(&on_set, &Options::default()).unwrap();
+        assert_eq!(result.len(), 4);
+        assert_eq!(result.phases, [true, true]);
+
+        let options = Options {
+            phase: true,
+            ..Options::default()
+        };
+        let result = minimize(&on_set, &options).unwrap();
+        assert_eq!(result.phases, [false, true]);
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().any(|c| c.to_string() == "000 10"));
+        assert_eq!(result.to_pla()[2], "#.phase 01");
+
+        /* a lone output too */
+        let result = minimize(&cover(3, &["1-- 1", "-1- 1", "--1 1"]), &options).unwrap();
+        assert_eq!(
+            result.to_pla(),
+            [".i 3", ".o 1", "#.phase 0", "000 1", ".e"]
+        );
+    }
+
+    #[test]
+    fn test_input_pairing() {
+        /* (a^b)&c takes two cubes, one once a and b are decoded together */
+        let on_set = cover(3, &["101 1", "011 1"]);
+        let result = minimize(&on_set, &Options::default()).unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(result.pairs.is_empty());
+
+        let options = Options {
+            pair: true,
+            ..Options::default()
+        };
+        let result = minimize(&on_set, &options).unwrap();
+        assert_eq!(result.pairs, [(0, 1)]);
+        assert_eq!(
+            result.to_pla(),
+            [".i 3", ".o 1", "#.pair 1 (1 2)", "--1 0110 1", ".e"]
+        );
+
+        /* nothing to gain from pairing any two inputs of a&b|c */
+        let result = minimize(&cover(3, &["11- 1", "--1 1"]), &options).unwrap();
+        assert!(result.pairs.is_empty());
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_calls() {
+        /* a different PLA size per thread, so overlapping runs would clash
+         * on the global cube structure */
+        let handles: Vec<_> = (1..=16)
+            .map(|n: usize| {
+                thread::spawn(move || {
+                    /* x0 | x1 | ... one cube per input */
+                    let rows: Vec<String> = (0..n)
+                        .map(|i| {
+                            let inputs: String =
+                                (0..n).map(|j| if i == j { '1' } else { '-' }).collect();
+                            format!("{} 1", inputs)
+                        })
+                        .collect();
+                    let rows: Vec<&str> = rows.iter().map(|r| r.as_str()).collect();
+                    (0..20)
+                        .map(|_| {
+                            minimize(&cover(n, &rows), &Options::default())
+                                .unwrap()
+                                .len()
+                        })
+                        .collect::<Vec<usize>>()
+                })
+            })
+            .collect();
+        for (i, h) in handles.into_iter().enumerate() {
+            assert!(h.join().unwrap().iter().all(|len| *len == i + 1));
+        }
+    }
 }
@@ -0,0 +1,1222 @@
+use crate::ast::{
+    BinaryOp, Direction, Expr, ExprKind, ItemKind, Module, Port, Range, Select, Span, UnaryOp,
+};
+use crate::bdd::{Bdd, Manager};
+use crate::bitblast;
+use crate::diagnostic::Diagnostic;
+use crate::espresso::Cover;
+use std::collections::HashMap;
+
+/// Declared signal: `name[msb:lsb]`, or a plain scalar when `range` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signal {
+    pub name: String,
+    pub range: Option<(usize, usize)>,
+    bits: Vec<Bdd>,
+}
+
+impl Signal {
+    pub fn width(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Function of every bit, LSB first.
+    pub fn bits(&self) -> &[Bdd] {
+        &self.bits
+    }
+
+    /* position of bit `index` inside `bits`, which is stored LSB first */
+    fn offset(&self, index: usize) -> Option<usize> {
+        match self.range {
+            None => (index == 0).then_some(0),
+            Some((msb, lsb)) if msb >= lsb => (lsb..=msb).contains(&index).then(|| index - lsb),
+            Some((msb, lsb)) => (msb..=lsb).contains(&index).then(|| lsb - index),
+        }
+    }
+
+    /// Name of bit `offset` (counted from the LSB) as it appears in the PLA
+    /// labels and in the netlist.
+    pub fn bit_name(&self, offset: usize) -> String {
+        match self.range {
+            None => self.name.clone(),
+            Some((msb, lsb)) if msb >= lsb => format!("{}[{}]", self.name, lsb + offset),
+            Some((_, lsb)) => format!("{}[{}]", self.name, lsb - offset),
+        }
+    }
+}
+
+/// Evaluation context of one expression or module: it owns the BDD manager
+/// and the declared signals, so nothing leaks between parses or threads.
+///
+/// Expressions evaluate to bit vectors (LSB first) following the Verilog
+/// sizing rules: the width of an expression is the maximum of its own
+/// self-determined width and the width of its context, operands are
+/// zero-extended to it and the result is truncated on assignment.
+#[derive(Default)]
+pub struct Context {
+    manager: Manager,
+    signals: Vec<Signal>,
+    index: HashMap<String, usize>,
+    unknowns: Vec<usize>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    pub fn manager(&mut self) -> &mut Manager {
+        &mut self.manager
+    }
+
+    pub fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.index.get(name).map(|i| &self.signals[*i])
+    }
+
+    /// Declares `name` with an optional `[msb:lsb]` range and creates one
+    /// BDD variable per bit. Redeclaring an existing signal is a no-op.
+    pub fn declare(&mut self, name: &str, range: Option<(usize, usize)>) {
+        if self.index.contains_key(name) {
+            return;
+        }
+        let mut signal = Signal {
+            name: name.to_string(),
+            range,
+            bits: Vec::new(),
+        };
+        for offset in 0..range_width(range) {
+            let bit = self.manager.var(&signal.bit_name(offset));
+            signal.bits.push(bit);
+        }
+        self.insert(signal);
+    }
+
+    /* a net driven by logic: its bits are functions, not fresh variables */
+    fn define(&mut self, name: &str, range: Option<(usize, usize)>, bits: Vec<Bdd>) {
+        self.insert(Signal {
+            name: name.to_string(),
+            range,
+            bits,
+        });
+    }
+
+    fn insert(&mut self, signal: Signal) {
+        self.index.insert(signal.name.clone(), self.signals.len());
+        self.signals.push(signal);
+    }
+
+    /// Declares a module port (`input [7:0] a`).
+    pub fn declare_port(&mut self, port: &Port) -> Result<(), Diagnostic> {
+        let range = self.range(&port.range)?;
+        self.declare(&port.name, range);
+        Ok(())
+    }
+
+    fn range(&mut self, range: &Option<Range>) -> Result<Option<(usize, usize)>, Diagnostic> {
+        match range {
+            None => Ok(None),
+            Some(r) => Ok(Some((self.constant(&r.msb)?, self.constant(&r.lsb)?))),
+        }
+    }
+
+    /// Elaborates a module: its inputs become variables and every `assign`
+    /// is evaluated, the wires it reads first, so each output port ends up
+    /// as a function of the inputs alone.
+    pub fn elaborate(&mut self, module: &Module) -> Result<(), Diagnostic> {
+        let mut nets = Nets::default();
+        for port in module.ports.iter() {
+            match port.direction {
+                Direction::Input => self.declare_port(port)?,
+                Direction::Output => {
+                    let range = self.range(&port.range)?;
+                    nets.ranges.insert(port.name.clone(), (range, port.span));
+                }
+            }
+        }
+        for item in module.items.iter() {
+            match &item.kind {
+                ItemKind::Port(_) => (),
+                ItemKind::Wire(r, name) | ItemKind::Reg(r, name) => {
+                    let range = self.range(r)?;
+                    /* `input a; wire a;` and `output y; wire y;` only restate
+                     * the port, which keeps its range */
+                    let declared = match self.signal(name) {
+                        Some(input) => Some(input.range),
+                        None => nets.ranges.get(name).map(|(range, _)| *range),
+                    };
+                    match declared {
+                        None => {
+                            nets.ranges.insert(name.clone(), (range, item.span));
+                        }
+                        Some(declared) if declared != range => {
+                            return Err(Diagnostic::new(
+                                &format!("`{}` is redeclared with a different range", name),
+                                item.span,
+                            ));
+                        }
+                        Some(_) => (),
+                    }
+                }
+                ItemKind::Assign(name, e) => {
+                    if !nets.ranges.contains_key(name) {
+                        let message = match self.signal(name) {
+                            Some(_) => format!("cannot assign to input `{}`", name),
+                            None => format!("undeclared identifier `{}`", name),
+                        };
+                        return Err(Diagnostic::new(&message, item.span));
+                    }
+                    if nets.drivers.insert(name.clone(), (e, item.span)).is_some() {
+                        return Err(Diagnostic::new(
+                            &format!("`{}` has more than one driver", name),
+                            item.span,
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut visiting: Vec<String> = Vec::new();
+        for port in module.ports.iter() {
+            if port.direction == Direction::Output {
+                self.resolve(&port.name, &nets, &mut visiting)?;
+            }
+        }
+        /* wires that feed no output still have to be well-formed */
+        for item in module.items.iter() {
+            if let ItemKind::Assign(name, _) = &item.kind {
+                self.resolve(name, &nets, &mut visiting)?;
+            }
+        }
+        Ok(())
+    }
+
+    /* defines net `name` from its assign once everything it reads is defined */
+    fn resolve(
+        &mut self,
+        name: &str,
+        nets: &Nets,
+        visiting: &mut Vec<String>,
+    ) -> Result<(), Diagnostic> {
+        if self.signal(name).is_some() {
+            return Ok(());
+        }
+        let (range, declaration) = nets.ranges[name];
+        let (tree, span) = match nets.drivers.get(name) {
+            Some(d) => *d,
+            None => {
+                return Err(Diagnostic::new(
+                    &format!("`{}` is never assigned", name),
+                    declaration,
+                ))
+            }
+        };
+        if visiting.iter().any(|n| n == name) {
+            return Err(Diagnostic::new(
+                &format!("combinational loop through `{}`", name),
+                span,
+            ));
+        }
+
+        visiting.push(name.to_string());
+        let mut order: Vec<String> = Vec::new();
+        self.collect_identifiers(tree, &mut order, &mut HashMap::new())?;
+        for n in order.iter() {
+            if nets.ranges.contains_key(n) {
+                self.resolve(n, nets, visiting)?;
+            }
+        }
+        visiting.pop();
+
+        let bits = self.eval_to(tree, range_width(range))?;
+        self.define(name, range, bits);
+        Ok(())
+    }
+
+    /// Declares every identifier of a free-standing expression that is not
+    /// declared yet. Identifiers used with a select get `[n:0]` with `n`
+    /// the highest index selected, all others are scalars.
+    pub fn declare_inputs(&mut self, tree: &Expr) -> Result<(), Diagnostic> {
+        let mut order: Vec<String> = Vec::new();
+        let mut highest: HashMap<String, Option<usize>> = HashMap::new();
+        self.collect_identifiers(tree, &mut order, &mut highest)?;
+        for name in order.iter() {
+            match highest.get(name).unwrap() {
+                None => self.declare(name, None),
+                Some(msb) => self.declare(name, Some((*msb, 0))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of a constant expression, `None` if it depends on a signal; it
+    /// is an error when the value does not fit in a `usize`.
+    pub fn const_value(&mut self, tree: &Expr) -> Result<Option<usize>, Diagnostic> {
+        let bits = self.eval(tree)?;
+        let mut value: usize = 0;
+        for (i, b) in bits.iter().enumerate() {
+            match *b {
+                Bdd::FALSE => (),
+                Bdd::TRUE if i < usize::BITS as usize => value |= 1 << i,
+                Bdd::TRUE => return Err(Diagnostic::new("constant too large", tree.span)),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /* like const_value, but a signal-dependent value is an error */
+    fn constant(&mut self, tree: &Expr) -> Result<usize, Diagnostic> {
+        match self.const_value(tree)? {
+            Some(v) => Ok(v),
+            None => Err(Diagnostic::new("expected a constant expression", tree.span)),
+        }
+    }
+
+    /* a fresh variable for an `x` or `z` literal bit; its name is no legal
+     * identifier, so it never clashes with a signal */
+    fn unknown(&mut self) -> Bdd {
+        let bit = self.manager.var(&format!("'x{}", self.unknowns.len()));
+        self.unknowns.push(self.manager.num_vars() - 1);
+        bit
+    }
+
+    /// Splits `f` into its on-set and its don't-care set. Where the value of
+    /// `f` depends on an `x` or `z` literal bit, either value is fine.
+    pub fn dont_cares(&mut self, f: Bdd) -> (Bdd, Bdd) {
+        let unknowns = self.unknowns.clone();
+        let on = self.manager.forall(f, &unknowns);
+        let maybe = self.manager.exists(f, &unknowns);
+        let dc = self.manager.and(maybe, !on);
+        (on, dc)
+    }
+
+    /// A don't-care condition such as `!valid`: true where `tree` is
+    /// certainly nonzero.
+    pub fn constraint(&mut self, tree: &Expr) -> Result<Bdd, Diagnostic> {
+        let bits = self.eval(tree)?;
+        let any = self.reduce_or(&bits);
+        let unknowns = self.unknowns.clone();
+        Ok(self.manager.forall(any, &unknowns))
+    }
+
+    /// Self-determined bit length of `tree`.
+    pub fn width(&mut self, tree: &Expr) -> Result<usize, Diagnostic> {
+        let width = match &tree.kind {
+            ExprKind::Identifier(i) => self.lookup(i, tree.span)?.width(),
+            ExprKind::Select(i, select) => {
+                let (lsb, msb) = self.select(i, select, tree.span)?;
+                msb - lsb + 1
+            }
+            ExprKind::Number(n) => literal_bits(n).len(),
+            ExprKind::Unary(UnaryOp::Not, e) => self.width(e)?,
+            ExprKind::Unary(_, _) => 1,
+            ExprKind::Binary(
+                BinaryOp::LogicAnd
+                | BinaryOp::LogicOr
+                | BinaryOp::Eq
+                | BinaryOp::Ne
+                | BinaryOp::CaseEq
+                | BinaryOp::CaseNe
+                | BinaryOp::Lt
+                | BinaryOp::Le
+                | BinaryOp::Gt
+                | BinaryOp::Ge,
+                _,
+                _,
+            ) => 1,
+            /* the shift amount is self-determined and does not widen the result */
+            ExprKind::Binary(
+                BinaryOp::Shl | BinaryOp::Shr | BinaryOp::AShl | BinaryOp::AShr,
+                l,
+                _,
+            ) => self.width(l)?,
+            ExprKind::Binary(_, l, r) => self.width(l)?.max(self.width(r)?),
+            ExprKind::Conditional(_, t, f) => self.width(t)?.max(self.width(f)?),
+            ExprKind::Concat(l) => self.concat_width(l)?,
+            ExprKind::Replicate(n, l) => self.constant(n)? * self.concat_width(l)?,
+        };
+        Ok(width)
+    }
+
+    /// Evaluates `tree` at its self-determined width.
+    pub fn eval(&mut self, tree: &Expr) -> Result<Vec<Bdd>, Diagnostic> {
+        let width = self.width(tree)?;
+        self.eval_sized(tree, width)
+    }
+
+    /// Evaluates `tree` as the right-hand side of an assignment to a
+    /// `width`-bit target: sized by the wider of the two, then truncated.
+    pub fn eval_to(&mut self, tree: &Expr, width: usize) -> Result<Vec<Bdd>, Diagnostic> {
+        let size = self.width(tree)?.max(width);
+        let mut bits = self.eval_sized(tree, size)?;
+        bits.truncate(width);
+        Ok(bits)
+    }
+
+    fn eval_sized(&mut self, tree: &Expr, width: usize) -> Result<Vec<Bdd>, Diagnostic> {
+        let bits: Vec<Bdd> = match &tree.kind {
+            ExprKind::Identifier(i) => self.lookup(i, tree.span)?.bits.clone(),
+            ExprKind::Select(i, select) => {
+                let (lsb, msb) = self.select(i, select, tree.span)?;
+                self.lookup(i, tree.span)?.bits[lsb..=msb].to_vec()
+            }
+            ExprKind::Number(n) => literal_bits(n)
+                .iter()
+                .zip(literal_unknowns(n))
+                .map(|(b, x)| match x {
+                    true => self.unknown(),
+                    false => self.manager.constant(*b),
+                })
+                .collect(),
+            ExprKind::Unary(UnaryOp::Not, e) => {
+                self.eval_sized(e, width)?.iter().map(|b| !*b).collect()
+            }
+            ExprKind::Unary(op, e) => {
+                let operand = self.eval(e)?;
+                let r = match op {
+                    UnaryOp::And | UnaryOp::Nand => self.reduce_and(&operand),
+                    UnaryOp::Xor | UnaryOp::Xnor => self.reduce_xor(&operand),
+                    _ => self.reduce_or(&operand),
+                };
+                match op {
+                    UnaryOp::Or | UnaryOp::And | UnaryOp::Xor => vec![r],
+                    _ => vec![!r],
+                }
+            }
+            ExprKind::Binary(op @ (BinaryOp::LogicAnd | BinaryOp::LogicOr), l, r) => {
+                let l = self.eval(l)?;
+                let r = self.eval(r)?;
+                let (l, r) = (self.reduce_or(&l), self.reduce_or(&r));
+                if *op == BinaryOp::LogicAnd {
+                    vec![self.manager.and(l, r)]
+                } else {
+                    vec![self.manager.or(l, r)]
+                }
+            }
+            ExprKind::Binary(
+                op @ (BinaryOp::Eq
+                | BinaryOp::Ne
+                | BinaryOp::CaseEq
+                | BinaryOp::CaseNe
+                | BinaryOp::Lt
+                | BinaryOp::Le
+                | BinaryOp::Gt
+                | BinaryOp::Ge),
+                l,
+                r,
+            ) => {
+                /* the operands are sized against each other, not the context */
+                let size = self.width(l)?.max(self.width(r)?);
+                let l = self.eval_sized(l, size)?;
+                let r = self.eval_sized(r, size)?;
+                let m = &mut self.manager;
+                /* values are two-state, so === and !== match == and != */
+                let bit = match op {
+                    BinaryOp::Eq | BinaryOp::CaseEq => bitblast::equal(m, &l, &r),
+                    BinaryOp::Ne | BinaryOp::CaseNe => !bitblast::equal(m, &l, &r),
+                    BinaryOp::Lt => bitblast::less_than(m, &l, &r),
+                    BinaryOp::Gt => bitblast::less_than(m, &r, &l),
+                    BinaryOp::Le => !bitblast::less_than(m, &r, &l),
+                    _ => !bitblast::less_than(m, &l, &r),
+                };
+                vec![bit]
+            }
+            ExprKind::Binary(
+                op @ (BinaryOp::Shl | BinaryOp::Shr | BinaryOp::AShl | BinaryOp::AShr),
+                l,
+                r,
+            ) => {
+                let l = self.eval_sized(l, width)?;
+                let r = self.eval(r)?;
+                let m = &mut self.manager;
+                /* every operand is unsigned, so >>> shifts in zeros like >> */
+                match op {
+                    BinaryOp::Shl | BinaryOp::AShl => bitblast::shift_left(m, &l, &r),
+                    _ => bitblast::shift_right(m, &l, &r, Bdd::FALSE),
+                }
+            }
+            ExprKind::Binary(op, l, r) => {
+                let l = self.eval_sized(l, width)?;
+                let r = self.eval_sized(r, width)?;
+                let m = &mut self.manager;
+                match op {
+                    BinaryOp::And => l.iter().zip(r.iter()).map(|(a, b)| m.and(*a, *b)).collect(),
+                    BinaryOp::Or => l.iter().zip(r.iter()).map(|(a, b)| m.or(*a, *b)).collect(),
+                    BinaryOp::Xor => l.iter().zip(r.iter()).map(|(a, b)| m.xor(*a, *b)).collect(),
+                    BinaryOp::Xnor => l
+                        .iter()
+                        .zip(r.iter())
+                        .map(|(a, b)| m.xnor(*a, *b))
+                        .collect(),
+                    BinaryOp::Add => bitblast::add(m, &l, &r),
+                    BinaryOp::Sub => bitblast::sub(m, &l, &r),
+                    BinaryOp::Mul => bitblast::mul(m, &l, &r),
+                    BinaryOp::Div => bitblast::div_rem(m, &l, &r).0,
+                    _ => bitblast::div_rem(m, &l, &r).1,
+                }
+            }
+            ExprKind::Conditional(c, t, f) => {
+                let c = self.eval(c)?;
+                let sel = self.reduce_or(&c);
+                let t = self.eval_sized(t, width)?;
+                let f = self.eval_sized(f, width)?;
+                bitblast::mux(&mut self.manager, sel, &t, &f)
+            }
+            ExprKind::Concat(l) => self.concat(l)?,
+            ExprKind::Replicate(n, l) => {
+                let count = self.constant(n)?;
+                let unknowns = self.unknowns.len();
+                let mut bits = self.concat(l)?;
+                if count == 0 || self.unknowns.len() == unknowns {
+                    bits.repeat(count)
+                } else {
+                    /* every copy of an x or z bit is free on its own */
+                    for _ in 1..count {
+                        let copy = self.concat(l)?;
+                        bits.extend(copy);
+                    }
+                    bits
+                }
+            }
+        };
+        Ok(resize(bits, width))
+    }
+
+    fn concat_width(&mut self, parts: &[Expr]) -> Result<usize, Diagnostic> {
+        let mut width = 0;
+        for e in parts.iter() {
+            width += self.width(e)?;
+        }
+        Ok(width)
+    }
+
+    /* every part is self-determined; the last one ends up in the low bits */
+    fn concat(&mut self, parts: &[Expr]) -> Result<Vec<Bdd>, Diagnostic> {
+        let mut bits: Vec<Bdd> = Vec::new();
+        for e in parts.iter().rev() {
+            let part = self.eval(e)?;
+            bits.extend(part);
+        }
+        Ok(bits)
+    }
+
+    /* the `[first:last]` bounds a select names, in the order they are written */
+    fn select_bounds(&mut self, select: &Select) -> Result<(usize, usize), Diagnostic> {
+        let bounds = match select {
+            Select::Bit(i) => {
+                let i = self.constant(i)?;
+                (i, i)
+            }
+            Select::Part(m, l) => (self.constant(m)?, self.constant(l)?),
+            Select::Up(b, w) | Select::Down(b, w) => {
+                let base = self.constant(b)?;
+                let width = self.constant(w)?;
+                if width == 0 {
+                    return Err(Diagnostic::new(
+                        "part-select width must be positive",
+                        w.span,
+                    ));
+                }
+                let low = match select {
+                    Select::Up(_, _) => base,
+                    _ => match base.checked_sub(width - 1) {
+                        Some(low) => low,
+                        None => {
+                            let span = b.span.to(w.span);
+                            return Err(Diagnostic::new("part-select runs below index 0", span));
+                        }
+                    },
+                };
+                (low + width - 1, low)
+            }
+        };
+        Ok(bounds)
+    }
+
+    /* range-checked (lsb, msb) offsets into the bits of `name` picked by `select` */
+    fn select(
+        &mut self,
+        name: &str,
+        select: &Select,
+        span: Span,
+    ) -> Result<(usize, usize), Diagnostic> {
+        let (first, last) = self.select_bounds(select)?;
+        let signal = self.lookup(name, span)?;
+        /* an indexed part-select follows the direction of the declaration */
+        let (first, last) = match (select, signal.range) {
+            (Select::Up(_, _) | Select::Down(_, _), Some((msb, lsb))) if msb < lsb => (last, first),
+            _ => (first, last),
+        };
+        let out_of_range = |index: usize| {
+            let declared = match signal.range {
+                None => String::from("a scalar"),
+                Some((msb, lsb)) => format!("declared as [{}:{}]", msb, lsb),
+            };
+            Diagnostic::new(
+                &format!(
+                    "index {} is out of range, `{}` is {}",
+                    index, signal.name, declared
+                ),
+                span,
+            )
+        };
+        let high = signal.offset(first).ok_or_else(|| out_of_range(first))?;
+        let low = signal.offset(last).ok_or_else(|| out_of_range(last))?;
+        if high < low {
+            return Err(Diagnostic::new(
+                &format!(
+                    "part-select of `{}` is reversed against its declaration",
+                    signal.name
+                ),
+                span,
+            ));
+        }
+        Ok((low, high))
+    }
+
+    fn lookup(&self, name: &str, span: Span) -> Result<&Signal, Diagnostic> {
+        match self.signal(name) {
+            Some(s) => Ok(s),
+            None => Err(Diagnostic::new(
+                &format!("undeclared identifier `{}`", name),
+                span,
+            )),
+        }
+    }
+
+    /* walks `tree` for declare_inputs, recording the highest selected index */
+    fn collect_identifiers(
+        &mut self,
+        tree: &Expr,
+        order: &mut Vec<String>,
+        highest: &mut HashMap<String, Option<usize>>,
+    ) -> Result<(), Diagnostic> {
+        let (name, index) = match &tree.kind {
+            ExprKind::Identifier(i) => (i, None),
+            ExprKind::Select(i, select) => {
+                let (first, last) = self.select_bounds(select)?;
+                (i, Some(first.max(last)))
+            }
+            _ => return self.collect_operands(tree, order, highest),
+        };
+        if !highest.contains_key(name) {
+            order.push(name.clone());
+        }
+        let entry = highest.entry(name.clone()).or_insert(index);
+        *entry = (*entry).max(index);
+        Ok(())
+    }
+
+    fn collect_operands(
+        &mut self,
+        tree: &Expr,
+        order: &mut Vec<String>,
+        highest: &mut HashMap<String, Option<usize>>,
+    ) -> Result<(), Diagnostic> {
+        match &tree.kind {
+            ExprKind::Identifier(_) | ExprKind::Select(_, _) => (),
+            ExprKind::Number(_) => (),
+            ExprKind::Unary(_, e) => self.collect_identifiers(e, order, highest)?,
+            ExprKind::Binary(_, l, r) => {
+                self.collect_identifiers(l, order, highest)?;
+                self.collect_identifiers(r, order, highest)?;
+            }
+            ExprKind::Conditional(c, t, f) => {
+                self.collect_identifiers(c, order, highest)?;
+                self.collect_identifiers(t, order, highest)?;
+                self.collect_identifiers(f, order, highest)?;
+            }
+            ExprKind::Concat(l) => {
+                for e in l.iter() {
+                    self.collect_identifiers(e, order, highest)?;
+                }
+            }
+            ExprKind::Replicate(n, l) => {
+                self.collect_identifiers(n, order, highest)?;
+                for e in l.iter() {
+                    self.collect_identifiers(e, order, highest)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn reduce_and(&mut self, bits: &[Bdd]) -> Bdd {
+        bits.iter()
+            .fold(Bdd::TRUE, |acc, b| self.manager.and(acc, *b))
+    }
+
+    fn reduce_or(&mut self, bits: &[Bdd]) -> Bdd {
+        bits.iter()
+            .fold(Bdd::FALSE, |acc, b| self.manager.or(acc, *b))
+    }
+
+    fn reduce_xor(&mut self, bits: &[Bdd]) -> Bdd {
+        bits.iter()
+            .fold(Bdd::FALSE, |acc, b| self.manager.xor(acc, *b))
+    }
+
+    pub fn create_cover(&mut self, fs: &[Bdd], dcs: &[Bdd]) -> (Cover, Cover, Vec<String>) {
+        self.manager.create_cover(fs, dcs)
+    }
+
+    pub fn create_truthtable(&mut self, fs: &[Bdd]) -> (Vec<String>, Vec<String>) {
+        self.manager.create_truthtable(fs)
+    }
+}
+
+/* nets of a module being elaborated: declared ranges and assign drivers */
+#[derive(Default)]
+struct Nets<'a> {
+    ranges: HashMap<String, (Option<(usize, usize)>, Span)>,
+    drivers: HashMap<String, (&'a Expr, Span)>,
+}
+
+fn range_width(range: Option<(usize, usize)>) -> usize {
+    match range {
+        None => 1,
+        Some((msb, lsb)) => msb.abs_diff(lsb) + 1,
+    }
+}
+
+/* zero-extends or truncates an LSB-first vector to `width` bits */
+fn resize(mut bits: Vec<Bdd>, width: usize) -> Vec<Bdd> {
+    bits.resize(width, Bdd::FALSE);
+    bits
+}
+
+/// Bits of a Verilog number literal, LSB first. Sized literals are
+/// truncated or zero-extended to their size, unsized ones are 32 bits wide
+/// and a leading sign gives the two's complement.
+pub fn literal_bits(text: &str) -> Vec<bool> {
+    if let Some(magnitude) = text.strip_prefix('-') {
+        /* -x == ~x + 1 */
+        let mut bits: Vec<bool> = literal_bits(magnitude).iter().map(|b| !b).collect();
+        for b in bits.iter_mut() {
+            *b = !*b;
+            if *b {
+                break;
+            }
+        }
+        return bits;
+    }
+    let text: String = text
+        .trim_start_matches('+')
+        .chars()
+        .filter(|c| *c != '_')
+        .collect();
+    let (size, radix, digits) = match text.find('\'') {
+        None => (None, 10, text.as_str()),
+        Some(p) => {
+            let size = text[..p].parse::<usize>().ok();
+            let radix = match text[p + 1..p + 2].to_lowercase().as_str() {
+                "b" => 2,
+                "o" => 8,
+                "h" => 16,
+                _ => 10,
+            };
+            (size, radix, &text[p + 2..])
+        }
+    };
+
+    let mut bits: Vec<bool> = Vec::new();
+    for c in digits.chars() {
+        /* bits = bits * radix + digit */
+        /* x, z and ? digits read as 0, literal_unknowns marks them */
+        let mut carry: u32 = c.to_digit(radix).unwrap_or(0);
+        for b in bits.iter_mut() {
+            let v = (*b as u32) * radix + carry;
+            *b = v & 1 == 1;
+            carry = v >> 1;
+        }
+        while carry > 0 {
+            bits.push(carry & 1 == 1);
+            carry >>= 1;
+        }
+    }
+    bits.resize(size.unwrap_or(32), false);
+    bits
+}
+
+/// Mask of the `x` and `z` bits of a literal, LSB first and as wide as
+/// `literal_bits`; `?` is a `z`. A leading `x` or `z` digit extends to the
+/// full size.
+pub fn literal_unknowns(text: &str) -> Vec<bool> {
+    let width = literal_bits(text).len();
+    let text: String = text.chars().filter(|c| *c != '_').collect();
+    let (radix, digits) = match text.find('\'') {
+        None => return vec![false; width],
+        Some(p) => (text[p + 1..p + 2].to_lowercase(), &text[p + 2..]),
+    };
+    let unknown = |c: char| matches!(c, 'x' | 'X' | 'z' | 'Z' | '?');
+    let per_digit = match radix.as_str() {
+        "b" => 1,
+        "o" => 3,
+        "h" => 4,
+        /* a decimal literal is either all x or a plain number */
+        _ => return vec![digits.chars().any(unknown); width],
+    };
+
+    let mut mask: Vec<bool> = digits
+        .chars()
+        .rev()
+        .flat_map(|c| std::iter::repeat_n(unknown(c), per_digit))
+        .collect();
+    mask.resize(width, digits.chars().next().is_some_and(unknown));
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{ExprKind, TreeNode};
+    use crate::bdd::Bdd;
+    use crate::bitblast;
+    use crate::espresso::{espresso_minimizer, Options};
+    use crate::eval::{literal_bits, literal_unknowns, Context};
+    use crate::verilog;
+    use std::thread;
+
+    #[test]
+    fn test_literal_bits() {
+        assert_eq!(literal_bits("4'b1010"), [false, true, false, true]);
+        assert_eq!(literal_bits("2'hff"), [true, true]);
+        assert_eq!(literal_bits("8'd200"), literal_bits("8'hc8"));
+        assert_eq!(literal_bits("'o7").len(), 32);
+        assert_eq!(literal_bits("100")[..8], literal_bits("8'h64")[..]);
+        assert_eq!(literal_bits("-1"), [true; 32]);
+        assert_eq!(literal_bits("-6")[..4], [false, true, false, true]);
+        assert_eq!(literal_bits("+6"), literal_bits("6"));
+
+        assert_eq!(literal_bits("4'b1x0z"), [false, false, false, true]);
+        assert_eq!(literal_unknowns("4'b1x0z"), [true, false, true, false]);
+        assert_eq!(
+            literal_unknowns("8'hx1"),
+            [false; 4]
+                .iter()
+                .chain(&[true; 4])
+                .copied()
+                .collect::<Vec<bool>>()
+        );
+        assert_eq!(
+            literal_unknowns("6'bx1"),
+            [false, true, true, true, true, true]
+        );
+        assert_eq!(literal_unknowns("'dx"), [true; 32]);
+        assert_eq!(literal_unknowns("4'd9"), [false; 4]);
+        assert_eq!(literal_bits("4'b1?0?"), literal_bits("4'b1z0z"));
+        assert_eq!(literal_unknowns("4'b1?0?"), literal_unknowns("4'b1z0z"));
+        assert_eq!(literal_unknowns("8'h?1"), literal_unknowns("8'hz1"));
+        assert_eq!(literal_unknowns("'d?"), [true; 32]);
+    }
+
+    #[test]
+    fn test_constant_propagation() {
+        let mut ctx = Context::new();
+        let tree = verilog::ExprParser::new()
+            .parse("(1'b1&v)|(~u&(&m| |start)&t)|(1'b0&w)")
+            .unwrap();
+        ctx.declare_inputs(&tree).unwrap();
+        let f = ctx.eval(&tree).unwrap()[0];
+
+        /* w is masked by a constant and drops out of the PLA */
+        let (table, inputs) = ctx.create_truthtable(&[f]);
+        assert_eq!(inputs, ["v", "u", "m", "start", "t"]);
+        assert_eq!(table[0], ".i 5");
+
+        let tree = verilog::ExprParser::new().parse("~2'b01 & 4'hf").unwrap();
+        assert_eq!(ctx.const_value(&tree).unwrap(), Some(0b1110));
+        let tree = verilog::ExprParser::new().parse("v | ~v").unwrap();
+        assert_eq!(ctx.eval(&tree).unwrap(), [Bdd::TRUE]);
+    }
+
+    #[test]
+    fn test_vector_widths() {
+        let module = verilog::Module_parametersParser::new()
+            .parse("input [7:0] a")
+            .unwrap();
+        let mut ctx = Context::new();
+        ctx.declare_port(&module).unwrap();
+        ctx.declare("b", None);
+        assert_eq!(ctx.signal("a").unwrap().width(), 8);
+
+        /* b is zero-extended to the width of a, the literal truncated to 4 */
+        let tree = verilog::ExprParser::new().parse("(a | b) & 8'hf0").unwrap();
+        let bits = ctx.eval_to(&tree, 4).unwrap();
+        assert_eq!(bits, [Bdd::FALSE; 4]);
+
+        let tree = verilog::ExprParser::new().parse("~a[3]").unwrap();
+        let bits = ctx.eval_to(&tree, 2).unwrap();
+        let a3 = ctx.signal("a").unwrap().bits[3];
+        assert_eq!(bits, [!a3, Bdd::TRUE]);
+
+        /* context-determined: the complement is taken at the target width */
+        let bits = ctx.eval_to(&tree, 1).unwrap();
+        assert_eq!(bits, [!a3]);
+    }
+
+    #[test]
+    fn test_reductions() {
+        let mut ctx = Context::new();
+        ctx.declare("m", Some((3, 0)));
+        ctx.declare("start", Some((1, 0)));
+
+        let parse = |e: &str| verilog::ExprParser::new().parse(e).unwrap();
+        let all = ctx.eval(&parse("&m")).unwrap()[0];
+        let none = ctx.eval(&parse("~|m")).unwrap()[0];
+        let parity = ctx.eval(&parse("^m")).unwrap()[0];
+        let even = ctx.eval(&parse("~^m")).unwrap()[0];
+        let nand = ctx.eval(&parse("~&m")).unwrap()[0];
+        assert_eq!(even, !parity);
+        assert_eq!(nand, !all);
+        assert_eq!(ctx.manager().sat_count(all), 4.0);
+        assert_eq!(ctx.manager().sat_count(none), 4.0);
+        assert_eq!(ctx.manager().sat_count(parity), 32.0);
+
+        /* the reduction is self-determined, only the 1-bit result is extended */
+        let bits = ctx.eval_to(&parse("&m | |start"), 2).unwrap();
+        assert_eq!(bits[1], Bdd::FALSE);
+        assert_eq!(ctx.manager().sat_count(bits[0]), 49.0);
+    }
+
+    #[test]
+    fn test_conditional() {
+        let mut ctx = Context::new();
+        ctx.declare("s", None);
+        ctx.declare("t", None);
+        ctx.declare("a", Some((3, 0)));
+        ctx.declare("b", Some((1, 0)));
+
+        let parse = |e: &str| verilog::ExprParser::new().parse(e).unwrap();
+        /* right-associative and below || in precedence */
+        let tree = parse("s || t ? a : t ? b : 4'd9");
+        assert!(matches!(tree.kind, ExprKind::Conditional(_, _, _)));
+        let bits = ctx.eval(&tree).unwrap();
+        assert_eq!(bits.len(), 4);
+
+        let (s, t) = (
+            ctx.signal("s").unwrap().bits[0],
+            ctx.signal("t").unwrap().bits[0],
+        );
+        let a = ctx.signal("a").unwrap().bits.clone();
+        let b = ctx.signal("b").unwrap().bits.clone();
+        let m = ctx.manager();
+        let sel = m.or(s, t);
+        let nested = m.ite(t, b[0], Bdd::TRUE);
+        assert_eq!(bits[0], m.ite(sel, a[0], nested));
+        let nested = m.ite(t, Bdd::FALSE, Bdd::TRUE);
+        assert_eq!(bits[3], m.ite(sel, a[3], nested));
+    }
+
+    #[test]
+    fn test_dont_cares() {
+        let mut ctx = Context::new();
+        let parse = |e: &str| verilog::ExprParser::new().parse(e).unwrap();
+        let tree = parse("s ? a : 1'bx");
+        ctx.declare_inputs(&tree).unwrap();
+        let f = ctx.eval(&tree).unwrap()[0];
+        let (s, a) = (
+            ctx.signal("s").unwrap().bits[0],
+            ctx.signal("a").unwrap().bits[0],
+        );
+        let (on, dc) = ctx.dont_cares(f);
+        assert_eq!(on, ctx.manager().and(s, a));
+        assert_eq!(dc, !s);
+
+        /* the unknown bit never becomes a PLA input */
+        let (on_set, dc_set, inputs) = ctx.create_cover(&[on], &[dc]);
+        assert_eq!(inputs, ["s", "a"]);
+        assert_eq!((on_set.len(), dc_set.len()), (1, 1));
+
+        let tree = parse("!valid");
+        ctx.declare_inputs(&tree).unwrap();
+        let valid = ctx.signal("valid").unwrap().bits[0];
+        assert_eq!(ctx.constraint(&tree).unwrap(), !valid);
+        assert_eq!(ctx.constraint(&parse("2'b0x")).unwrap(), Bdd::FALSE);
+        assert!(ctx.const_value(&parse("4'b1x00")).unwrap().is_none());
+
+        /* `?` is a z digit inside a literal and the conditional outside */
+        let f = ctx.eval(&parse("s?a:1'b?")).unwrap()[0];
+        assert_eq!(ctx.dont_cares(f), (on, dc));
+        assert!(ctx.const_value(&parse("8'h?0")).unwrap().is_none());
+
+        /* the copies of a replicated unknown need not agree */
+        let f = ctx.eval(&parse("{2{1'bx}} == 2'b01")).unwrap()[0];
+        assert_eq!(ctx.dont_cares(f), (Bdd::FALSE, Bdd::TRUE));
+        let f = ctx.eval(&parse("{4{1'bx}} == 4'b1010")).unwrap()[0];
+        assert_eq!(ctx.dont_cares(f), (Bdd::FALSE, Bdd::TRUE));
+        let f = ctx
+            .eval(&parse("{2{s, 1'bz}} == {s, 1'b0, s, 1'b1}"))
+            .unwrap()[0];
+        assert_eq!(ctx.dont_cares(f), (Bdd::FALSE, Bdd::TRUE));
+    }
+
+    #[test]
+    fn test_relational_and_shift() {
+        let mut ctx = Context::new();
+        ctx.declare("a", Some((3, 0)));
+        ctx.declare("b", Some((3, 0)));
+
+        let parse = |e: &str| verilog::ExprParser::new().parse(e).unwrap();
+        /* & binds tighter than ^, which binds tighter than | */
+        let tree = parse("a | b ^ a & b");
+        let expected = parse("a | (b ^ (a & b))");
+        assert_eq!(ctx.eval(&tree).unwrap(), ctx.eval(&expected).unwrap());
+
+        /* shifts bind tighter than comparisons, comparisons than equality */
+        let tree = parse("a << 1 + 1 < b == 1'b1");
+        let expected = parse("((a << (1 + 1)) < b) == 1'b1");
+        assert_eq!(TreeNode::from(&tree), TreeNode::from(&expected));
+        assert_eq!(ctx.width(&tree).unwrap(), 1);
+
+        /* a < b matches b > a, a <= b matches b >= a */
+        let lt = ctx.eval(&parse("a < b")).unwrap()[0];
+        let gt = ctx.eval(&parse("b > a")).unwrap()[0];
+        let le = ctx.eval(&parse("a <= b")).unwrap()[0];
+        let ge = ctx.eval(&parse("b >= a")).unwrap()[0];
+        assert_eq!(lt, gt);
+        assert_eq!(le, ge);
+        assert_eq!(ctx.manager().sat_count(lt), 120.0);
+        assert_eq!(ctx.manager().sat_count(le), 136.0);
+        let eq = ctx.eval(&parse("a === b")).unwrap()[0];
+        assert_eq!(ctx.eval(&parse("a != b")).unwrap(), [!eq]);
+
+        /* the shifted operand is context-determined, the amount is not */
+        let tree = parse("6'd0 | 4'b1001 << 2'd2");
+        assert_eq!(ctx.const_value(&tree).unwrap(), Some(0b100100));
+        assert_eq!(ctx.const_value(&parse("8'hf0 >>> 3")).unwrap(), Some(0x1e));
+        assert_eq!(
+            ctx.const_value(&parse("4'b1100 ~^ 4'b1010")).unwrap(),
+            Some(0b1001)
+        );
+    }
+
+    #[test]
+    fn test_sign_operators() {
+        let mut ctx = Context::new();
+        ctx.declare("a", Some((3, 0)));
+        ctx.declare("x", Some((7, 0)));
+
+        /* an operator next to a number is never part of it */
+        let parse = |e: &str| verilog::ExprParser::new().parse(e).unwrap();
+        for (tight, spaced) in [
+            ("a+1", "a + 1"),
+            ("a-1", "a - 1"),
+            ("x[3:0]+1", "x[3:0] + 1"),
+        ] {
+            let tight = ctx.eval(&parse(tight)).unwrap();
+            assert_eq!(tight, ctx.eval(&parse(spaced)).unwrap());
+        }
+
+        /* -x is 0 - x at the width of x, +x is x */
+        assert_eq!(ctx.width(&parse("-a")).unwrap(), 4);
+        assert_eq!(ctx.const_value(&parse("-4'd3")).unwrap(), Some(13));
+        assert_eq!(ctx.const_value(&parse("- -4'd3")).unwrap(), Some(3));
+        assert_eq!(ctx.const_value(&parse("+4'd3")).unwrap(), Some(3));
+        assert_eq!(ctx.const_value(&parse("3+-1")).unwrap(), Some(2));
+        assert_eq!(
+            ctx.const_value(&parse("-1")).unwrap(),
+            Some(u32::MAX as usize)
+        );
+        let a = ctx.signal("a").unwrap().bits.clone();
+        let negated = bitblast::sub(ctx.manager(), &[Bdd::FALSE; 4], &a);
+        assert_eq!(ctx.eval(&parse("-a")).unwrap(), negated);
+    }
+
+    #[test]
+    fn test_concatenation() {
+        let mut ctx = Context::new();
+        let parse = |e: &str| verilog::ExprParser::new().parse(e).unwrap();
+        let tree = parse("{a, b[2], 3'b101} ^ {2{x, 1'b0}}");
+        ctx.declare_inputs(&tree).unwrap();
+        assert_eq!(ctx.width(&tree).unwrap(), 5);
+
+        let bits = ctx.eval(&tree).unwrap();
+        let a = ctx.signal("a").unwrap().bits[0];
+        let b2 = ctx.signal("b").unwrap().bits[2];
+        let x = ctx.signal("x").unwrap().bits[0];
+        let m = ctx.manager();
+        /* LSB first: 3'b101, b[2], a against 0, x, 0, x, zero-extended */
+        assert_eq!(bits[..3], [Bdd::TRUE, x, Bdd::TRUE]);
+        assert_eq!(bits[3], m.xor(b2, x));
+        assert_eq!(bits[4], a);
+
+        /* the replication count may be any constant expression */
+        assert_eq!(
+            ctx.const_value(&parse("{1 + 1{2'b10}}")).unwrap(),
+            Some(0b1010)
+        );
+    }
+
+    #[test]
+    fn test_part_select() {
+        let mut ctx = Context::new();
+        let port = verilog::Module_parametersParser::new()
+            .parse("input [7:0] a")
+            .unwrap();
+        ctx.declare_port(&port).unwrap();
+        ctx.declare("d", Some((0, 3)));
+        ctx.declare("s", None);
+
+        let parse = |e: &str| verilog::ExprParser::new().parse(e).unwrap();
+        let a = ctx.signal("a").unwrap().bits.clone();
+        let d = ctx.signal("d").unwrap().bits.clone();
+        assert_eq!(ctx.eval(&parse("a[7:4]")).unwrap(), a[4..8]);
+        assert_eq!(ctx.eval(&parse("a[2 +: 3]")).unwrap(), a[2..5]);
+        assert_eq!(ctx.eval(&parse("a[5 -: 3]")).unwrap(), a[3..6]);
+        assert_eq!(ctx.eval(&parse("a[1 + 2]")).unwrap(), [a[3]]);
+        /* ascending declarations select in their own direction */
+        assert_eq!(ctx.eval(&parse("d[0 +: 2]")).unwrap(), d[2..4]);
+        assert_eq!(ctx.eval(&parse("d[0:1]")).unwrap(), d[2..4]);
+
+        let error = |ctx: &mut Context, e: &str| ctx.eval(&parse(e)).unwrap_err();
+        let e = error(&mut ctx, "a[3] & a[8]");
+        assert_eq!(
+            e.message,
+            "index 8 is out of range, `a` is declared as [7:0]"
+        );
+        assert_eq!((e.span.start, e.span.end), (7, 11));
+        let e = error(&mut ctx, "a[6 +: 4]");
+        assert_eq!(
+            e.message,
+            "index 9 is out of range, `a` is declared as [7:0]"
+        );
+        let e = error(&mut ctx, "s[1]");
+        assert_eq!(e.message, "index 1 is out of range, `s` is a scalar");
+        let e = error(&mut ctx, "a[65'h10000000000000000]");
+        assert_eq!(e.message, "constant too large");
+        assert_eq!((e.span.start, e.span.end), (2, 23));
+        let e = error(&mut ctx, "{65'h10000000000000001{1'b1}}");
+        assert_eq!(e.message, "constant too large");
+        let e = error(&mut ctx, "a[3:4]");
+        assert_eq!(
+            e.message,
+            "part-select of `a` is reversed against its declaration"
+        );
+        let e = error(&mut ctx, "a[s]");
+        assert_eq!(e.message, "expected a constant expression");
+        let e = error(&mut ctx, "a[b]");
+        assert_eq!(e.message, "undeclared identifier `b`");
+
+        /* free-standing inputs are declared up to the highest selected index */
+        let tree = parse("x[3 +: 2] | y[1:0]");
+        ctx.declare_inputs(&tree).unwrap();
+        assert_eq!(ctx.signal("x").unwrap().range, Some((4, 0)));
+        assert_eq!(ctx.signal("y").unwrap().range, Some((1, 0)));
+    }
+
+    #[test]
+    fn test_elaborate_module() {
+        let parse = |s: &str| verilog::Module_scopeParser::new().parse(s).unwrap();
+        let module = parse(
+            "module m(a, b, y, z);
+                input [1:0] a;
+                input b;
+                output y;
+                output [1:0] z;
+                wire [1:0] t;
+                wire u;
+                assign y = u ^ b;
+                assign u = &t;
+                assign t = ~a;
+                assign z = {b, u};
+            endmodule",
+        );
+        let mut ctx = Context::new();
+        ctx.elaborate(&module).unwrap();
+
+        /* wires are flattened away, outputs only depend on inputs */
+        let a = ctx.signal("a").unwrap().bits.clone();
+        let b = ctx.signal("b").unwrap().bits[0];
+        let z = ctx.signal("z").unwrap().bits.clone();
+        let y = ctx.signal("y").unwrap().bits[0];
+        let m = ctx.manager();
+        let u = m.and(!a[0], !a[1]);
+        assert_eq!(y, m.xor(u, b));
+        assert_eq!(z, [u, b]);
+        assert_eq!(m.num_vars(), 3);
+
+        let error = |s: &str| Context::new().elaborate(&parse(s)).unwrap_err().message;
+        assert_eq!(
+            error("module m(input a, output y); wire t; assign y = t; endmodule"),
+            "`t` is never assigned"
+        );
+        assert_eq!(
+            error("module m(input a, output y); assign y = a; assign y = ~a; endmodule"),
+            "`y` has more than one driver"
+        );
+        assert_eq!(
+            error("module m(input a, output y); assign a = y; endmodule"),
+            "cannot assign to input `a`"
+        );
+        assert_eq!(
+            error("module m(input a, output y); wire t; assign t = t & a; assign y = t; endmodule"),
+            "combinational loop through `t`"
+        );
+        assert_eq!(
+            error("module m(a, y); input [1:0] a; wire a; output y; assign y = &a; endmodule"),
+            "`a` is redeclared with a different range"
+        );
+
+        /* a net declaration of an input port only restates it */
+        let module = parse(
+            "module m(a, b, y);
+                input a;
+                input b;
+                wire a;
+                output y;
+                assign y = a & b;
+            endmodule",
+        );
+        let mut ctx = Context::new();
+        ctx.elaborate(&module).unwrap();
+        let a = ctx.signal("a").unwrap().bits[0];
+        let b = ctx.signal("b").unwrap().bits[0];
+        let y = ctx.signal("y").unwrap().bits[0];
+        assert_eq!(y, ctx.manager().and(a, b));
+    }
+
+    #[test]
+    fn test_parallel_contexts() {
+        /* the whole live path, from the parse through the BDD manager and
+         * its variable map to the minimized cover, on one Context each */
+        fn run(expr: &str) -> (Vec<String>, Vec<String>, Vec<String>) {
+            let tree = verilog::ExprParser::new().parse(expr).unwrap();
+            let mut ctx = Context::new();
+            ctx.declare_inputs(&tree).unwrap();
+            let bits = ctx.eval(&tree).unwrap();
+            let (fs, dcs): (Vec<Bdd>, Vec<Bdd>) = bits.iter().map(|f| ctx.dont_cares(*f)).unzip();
+            let (table, inputs) = ctx.create_truthtable(&fs);
+            let (on_set, dc_set, _) = ctx.create_cover(&fs, &dcs);
+            let cover = espresso_minimizer(&on_set, &dc_set, &Options::default()).unwrap();
+            (table, inputs, cover.to_pla())
+        }
+
+        let exprs = [
+            "a&b",
+            "a|c",
+            "~a&d",
+            "(x[1]||y)&&z",
+            "{a ^ b, c & 2'b1x}",
+            "s ? a + b : ~c",
+        ];
+        let expected: Vec<_> = exprs.iter().map(|e| run(e)).collect();
+        assert_eq!(expected[0].0[2..], ["11 1", ".e"]);
+        assert_eq!(expected[1].1, ["a", "c"]);
+        assert_eq!(expected[2].0[2..], ["01 1", ".e"]);
+        assert_eq!(expected[3].1, ["x[1]", "y", "z"]);
+
+        let handles: Vec<_> = (0..32)
+            .map(|i| {
+                let expr = exprs[i % exprs.len()];
+                thread::spawn(move || run(expr))
+            })
+            .collect();
+        for (i, h) in handles.into_iter().enumerate() {
+            assert_eq!(h.join().unwrap(), expected[i % exprs.len()]);
+        }
+    }
+}
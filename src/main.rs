@@ -1,5 +1,3 @@
-#![feature(is_some_and)]
-
 #[macro_use]
 extern crate lalrpop_util;
 extern crate ptree;
@@ -10,16 +8,318 @@ use ptree::{Color, PrintConfig, Style};
 
 use std::env;
 
-use crate::espresso::espresso_minimizer;
-use crate::technology_map::technology_map_by_nand_nor;
+use crate::diagnostic::Diagnostic;
+use crate::espresso::{
+    espresso_minimizer, espresso_minimizer_from_path, Cover, EspressoError, Mode, Options,
+};
+use crate::technology_map::{technology_map_by_nand_nor, Function, Literal, Netlist, Port};
 
-lalrpop_mod!(pub verilog);
+lalrpop_mod!(#[allow(clippy::all)] pub verilog);
 pub mod ast;
 pub mod bdd;
+pub mod bitblast;
+pub mod diagnostic;
 pub mod espresso;
+pub mod eval;
 pub mod technology_map;
 
-fn parser_exp(expr: &str, path: Option<&str>) -> bool {
+/* command line settings shared by every run */
+#[derive(Default)]
+struct Settings {
+    options: Options,
+    /* --dont-care: where this expression holds, every output is free */
+    dont_care: Option<String>,
+}
+
+/* the literals a cube puts on the decoded pair (a, b) that may take the
+ * values `values`, bit 2a+b for (a, b): plain literals where the values
+ * form a subcube, otherwise one decoder line a|b' for every value left
+ * out, the sum that is 0 on exactly that value */
+fn pair_literals(a: usize, b: usize, values: u8) -> Vec<Literal> {
+    match values {
+        0b1111 => Vec::new(),
+        0b1100 => vec![Literal::Input(a, true)],
+        0b0011 => vec![Literal::Input(a, false)],
+        0b1010 => vec![Literal::Input(b, true)],
+        0b0101 => vec![Literal::Input(b, false)],
+        v if v.count_ones() == 1 => {
+            let v = v.trailing_zeros();
+            vec![Literal::Input(a, v & 2 != 0), Literal::Input(b, v & 1 != 0)]
+        }
+        _ => (0..4)
+            .filter(|v| values & (1 << v) == 0)
+            .map(|v| Literal::Decoder((a, v & 2 == 0), (b, v & 1 == 0)))
+            .collect(),
+    }
+}
+
+/* the output bits of a minimized cover, their literals indexing its
+ * inputs; a bit is negative when Espresso chose to realize its complement,
+ * and a shared term is the same literals in every bit */
+fn sum_of_products(cover: &Cover) -> Vec<Function> {
+    let mut terms: Vec<Vec<Vec<Literal>>> = vec![Vec::new(); cover.outputs];
+    for cube in cover.iter() {
+        let mut term: Vec<Literal> = cube
+            .inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, literal)| literal.map(|value| Literal::Input(i, value)))
+            .collect();
+        for ((a, b), values) in cover.pairs.iter().zip(cube.pairs.iter()) {
+            term.extend(pair_literals(*a, *b, *values));
+        }
+        for (k, output) in cube.outputs.iter().enumerate() {
+            if *output {
+                terms[k].push(term.clone());
+            }
+        }
+    }
+
+    /* no term at all, or one without literals, makes the bit a constant */
+    terms
+        .into_iter()
+        .zip(cover.phases.iter())
+        .map(|(terms, phase)| {
+            if terms.is_empty() || terms.iter().any(|t| t.is_empty()) {
+                Function::Constant(terms.is_empty() != *phase)
+            } else {
+                Function::SumOfProducts {
+                    terms,
+                    negative: !*phase,
+                }
+            }
+        })
+        .collect()
+}
+
+/* the cubes Espresso returned, under a header naming the inputs */
+fn print_cover(cover: &Cover, names: &[String]) {
+    println!("Espresso result: ");
+    for i in names.iter() {
+        print!("{}|", i);
+    }
+    println!();
+    if !cover.pairs.is_empty() {
+        let pairs: Vec<String> = cover
+            .pairs
+            .iter()
+            .map(|(a, b)| format!("({}, {})", names[*a], names[*b]))
+            .collect();
+        println!("Input pairs: {}", pairs.join(" "));
+    }
+    for cube in cover.iter() {
+        println!("{}", cube);
+    }
+}
+
+/* two-level minimization of all output bits as one multi-output PLA, so
+ * Espresso can share product terms between them, see sum_of_products for
+ * what every bit comes back as, along with the inputs its literals index.
+ * Bit i must be 1 on fs[i] and may be anything on dcs[i]. */
+fn minimize(
+    ctx: &mut eval::Context,
+    fs: &[bdd::Bdd],
+    dcs: &[bdd::Bdd],
+    options: &Options,
+) -> Result<(Vec<Function>, Vec<String>), EspressoError> {
+    /* a bit with no on-set is 0, one that is 1 or free everywhere is 1 */
+    let constant: Vec<Option<bool>> = fs
+        .iter()
+        .zip(dcs.iter())
+        .map(|(f, dc)| match *f {
+            bdd::Bdd::FALSE => Some(false),
+            _ if ctx.manager().or(*f, *dc) == bdd::Bdd::TRUE => Some(true),
+            _ => None,
+        })
+        .collect();
+    let mut expressions: Vec<Function> = constant
+        .iter()
+        .map(|c| Function::Constant(c.unwrap_or(false)))
+        .collect();
+    let columns: Vec<usize> = (0..fs.len()).filter(|i| constant[*i].is_none()).collect();
+    if columns.is_empty() {
+        return Ok((expressions, Vec::new()));
+    }
+
+    let functions: Vec<bdd::Bdd> = columns.iter().map(|i| fs[*i]).collect();
+    let dont_cares: Vec<bdd::Bdd> = columns.iter().map(|i| dcs[*i]).collect();
+    for f in functions.iter() {
+        println!("BDD nodes: {}", ctx.manager().node_count(*f));
+    }
+    let (on_set, dc_set, item_name) = ctx.create_cover(&functions, &dont_cares);
+    let espresso_output = espresso_minimizer(&on_set, &dc_set, options)?;
+    print_cover(&espresso_output, &item_name);
+    for (column, expression) in columns.iter().zip(sum_of_products(&espresso_output)) {
+        expressions[*column] = expression;
+    }
+    Ok((expressions, item_name))
+}
+
+/* the --dont-care condition over the signals of `ctx`, FALSE without one;
+ * a free-standing expression declares the names it introduces */
+fn dont_care(ctx: &mut eval::Context, settings: &Settings, declare: bool) -> Option<bdd::Bdd> {
+    let source = match &settings.dont_care {
+        Some(source) => source,
+        None => return Some(bdd::Bdd::FALSE),
+    };
+    let tree = match verilog::ExprParser::new().parse(source) {
+        Ok(tree) => tree,
+        Err(e) => {
+            print!("{}", Diagnostic::from(e).render(source));
+            return None;
+        }
+    };
+    let condition = match declare {
+        true => ctx
+            .declare_inputs(&tree)
+            .and_then(|_| ctx.constraint(&tree)),
+        false => ctx.constraint(&tree),
+    };
+    match condition {
+        Ok(c) => Some(c),
+        Err(e) => {
+            print!("{}", e.render(source));
+            None
+        }
+    }
+}
+
+/* the on-set and don't-care set of every output bit */
+fn care_sets(
+    ctx: &mut eval::Context,
+    outputs: &[(String, bdd::Bdd)],
+    dont_care: bdd::Bdd,
+) -> (Vec<bdd::Bdd>, Vec<bdd::Bdd>) {
+    let mut fs: Vec<bdd::Bdd> = Vec::new();
+    let mut dcs: Vec<bdd::Bdd> = Vec::new();
+    for (_, f) in outputs.iter() {
+        let (on, dc) = ctx.dont_cares(*f);
+        let m = ctx.manager();
+        fs.push(m.and(on, !dont_care));
+        dcs.push(m.or(dc, dont_care));
+    }
+    (fs, dcs)
+}
+
+/* the bits of a free-standing expression, driving "out" or "out[i]" */
+fn expression_outputs(bits: &[bdd::Bdd]) -> Vec<(String, bdd::Bdd)> {
+    bits.iter()
+        .enumerate()
+        .map(|(bit, f)| {
+            let target = if bits.len() > 1 {
+                format!("out[{}]", bit)
+            } else {
+                String::from("out")
+            };
+            (target, *f)
+        })
+        .collect()
+}
+
+/* minimizes the output bits together and maps them into one netlist module;
+ * an output may take any value where `dont_care` holds or it reads an x */
+fn synthesize(
+    ctx: &mut eval::Context,
+    module: &str,
+    ports: &[Port],
+    outputs: &[(String, bdd::Bdd)],
+    dont_care: bdd::Bdd,
+    path: Option<&str>,
+    options: &Options,
+) -> bool {
+    let (fs, dcs) = care_sets(ctx, outputs, dont_care);
+    let (expressions, inputs) = match minimize(ctx, &fs, &dcs, options) {
+        Ok(minimized) => minimized,
+        Err(e) => {
+            println!("error: {}", e);
+            println!("----------------------------------------------");
+            return false;
+        }
+    };
+    let functions: Vec<(String, Function)> = outputs
+        .iter()
+        .map(|(target, _)| target.clone())
+        .zip(expressions)
+        .collect();
+    let netlist = print_netlist(module, ports, &inputs, &functions, path);
+    let equivalent = check_equivalence(ctx, &netlist, outputs, &fs, &dcs);
+    println!("----------------------------------------------");
+    equivalent
+}
+
+/* rebuilds every output bit from the mapped gates and compares it with its
+ * on-set fs[i] wherever dcs[i] does not leave it free; prints an input
+ * vector that tells them apart when they differ */
+fn check_equivalence(
+    ctx: &mut eval::Context,
+    netlist: &Netlist,
+    outputs: &[(String, bdd::Bdd)],
+    fs: &[bdd::Bdd],
+    dcs: &[bdd::Bdd],
+) -> bool {
+    println!("Equivalence Check:");
+    let mapped = netlist.functions(ctx.manager());
+    for (i, (target, _)) in outputs.iter().enumerate() {
+        let g = match mapped.iter().find(|(t, _)| t == target) {
+            Some((_, g)) => *g,
+            None => {
+                println!("error: the netlist does not drive {}", target);
+                return false;
+            }
+        };
+        let m = ctx.manager();
+        let difference = m.xor(g, fs[i]);
+        let difference = m.and(difference, !dcs[i]);
+        let cube = match m.sat_one(difference) {
+            Some(cube) => cube,
+            None => continue,
+        };
+
+        /* free inputs read 0; the x literal bits are no inputs */
+        let assignment: Vec<bool> = cube.iter().map(|v| v.unwrap_or(false)).collect();
+        let vector: Vec<String> = m
+            .var_names()
+            .iter()
+            .zip(assignment.iter())
+            .filter(|(name, _)| !name.starts_with('\''))
+            .map(|(name, value)| format!("{}={}", name.trim_end(), *value as u8))
+            .collect();
+        println!("error: the netlist differs from the source at {}", target);
+        println!("    counterexample: {}", vector.join(" "));
+        println!(
+            "    expected {}, the netlist gives {}",
+            m.eval(fs[i], &assignment) as u8,
+            m.eval(g, &assignment) as u8
+        );
+        return false;
+    }
+    println!("every output bit matches the source");
+    true
+}
+
+/* prints the minimized output bits and the netlist they map onto */
+fn print_netlist(
+    module: &str,
+    ports: &[Port],
+    inputs: &[String],
+    functions: &[(String, Function)],
+    path: Option<&str>,
+) -> Netlist {
+    println!("----------------------------------------------");
+    println!("Optimized Boolean Algebra:");
+    for (target, function) in functions.iter() {
+        println!("{}: {}", target, function.format(inputs));
+    }
+    println!("----------------------------------------------");
+    println!("Technology Mapping:");
+    let path = path.unwrap_or("./library.json");
+    let netlist = technology_map_by_nand_nor(module, ports, inputs, functions, path);
+    println!("\n\n{}", netlist);
+    println!("----------------------------------------------");
+    netlist
+}
+
+fn parser_exp(expr: &str, path: Option<&str>, settings: &Settings) -> bool {
     let config = {
         let mut config = PrintConfig::from_env();
         config.leaf = Style {
@@ -37,71 +337,58 @@ fn parser_exp(expr: &str, path: Option<&str>) -> bool {
         Ok(t) => {
             println!("AST Tree:");
             print_tree_with(&t, &config).unwrap();
-            let bnode = bdd::BNode::from(&t);
-            let (truthtable, item_name) = bnode.create_truthtable();
-            println!("{:?}", truthtable);
-            let espresso_output: Vec<String> = espresso_minimizer(truthtable);
-            println!("Espresso result: ");
-            for i in item_name.iter() {
-                print!("{}|", i);
-            }
-            println!();
-
-            let mut expression: String = String::from("f = ");
-
-            for i in espresso_output.iter() {
-                println!("{}", i);
-                for (j, _) in item_name.iter().enumerate() {
-                    match i.as_bytes()[j] as char {
-                        '0' => {
-                            expression.push('<');
-                            expression.push_str(item_name[j].as_str());
-                            expression.push('\'');
-                            expression.push('>');
-                        }
-                        '1' => {
-                            expression.push('<');
-                            expression.push_str(item_name[j].as_str());
-                            expression.push('>');
-                        }
-                        _ => (),
-                    }
+            let mut ctx = eval::Context::new();
+            let bits = match ctx.declare_inputs(&t).and_then(|_| ctx.eval(&t)) {
+                Ok(bits) => bits,
+                Err(e) => {
+                    print!("{}", e.render(expr));
+                    println!("----------------------------------------------");
+                    return false;
                 }
-                if i != espresso_output.iter().last().unwrap() {
-                    expression.push_str(" + ");
+            };
+            let dont_care = match dont_care(&mut ctx, settings, true) {
+                Some(c) => c,
+                None => {
+                    println!("----------------------------------------------");
+                    return false;
                 }
-            }
-
-            println!("----------------------------------------------");
-            println!("Optimized Boolean Algebra:");
-            println!("{}", expression);
-            println!("----------------------------------------------");
-            println!("Technology Mapping:");
+            };
 
-            if item_name.len() == 1 && expression.find('\'').is_none() {
-                println!("module test(input {}, output out);", item_name[0]);
-                println!("assign out = {};", item_name[0]);
-                println!("endmodule")
-            } else {
-                println!("{:?}", item_name);
-                println!(
-                    "\n\n{}",
-                    technology_map_by_nand_nor(expression, path.unwrap_or("./library.json"))
-                );
-            }
+            let mut ports: Vec<Port> = ctx
+                .signals()
+                .iter()
+                .map(|s| Port {
+                    name: s.name.clone(),
+                    output: false,
+                    range: s.range,
+                })
+                .collect();
+            ports.push(Port {
+                name: String::from("out"),
+                output: true,
+                range: (bits.len() > 1).then(|| (bits.len() - 1, 0)),
+            });
 
-            println!("----------------------------------------------");
-            true
+            let outputs = expression_outputs(&bits);
+            synthesize(
+                &mut ctx,
+                "test",
+                &ports,
+                &outputs,
+                dont_care,
+                path,
+                &settings.options,
+            )
         }
         Err(e) => {
-            println!("Error: {:?}", e);
+            print!("{}", Diagnostic::from(e).render(expr));
             println!("----------------------------------------------");
             false
         }
     }
 }
 
-fn parser_module(expr: &str) -> bool {
+fn parser_module(expr: &str, path: Option<&str>, settings: &Settings) -> bool {
     let config = {
         let mut config = PrintConfig::from_env();
         config.leaf = Style {
@@ -119,56 +406,419 @@ fn parser_module(expr: &str) -> bool {
         Ok(t) => {
             println!("AST Tree:");
             print_tree_with(&t, &config).unwrap();
-            println!("----------------------------------------------");
-            true
+            let mut ctx = eval::Context::new();
+            if let Err(e) = ctx.elaborate(&t) {
+                print!("{}", e.render(expr));
+                println!("----------------------------------------------");
+                return false;
+            }
+            let dont_care = match dont_care(&mut ctx, settings, false) {
+                Some(c) => c,
+                None => {
+                    println!("----------------------------------------------");
+                    return false;
+                }
+            };
+
+            /* the netlist keeps the ports of the source module, in order */
+            let mut ports: Vec<Port> = Vec::new();
+            let mut outputs: Vec<(String, bdd::Bdd)> = Vec::new();
+            for p in t.ports.iter() {
+                let signal = ctx.signal(&p.name).unwrap();
+                let output = p.direction == ast::Direction::Output;
+                if output {
+                    for (offset, f) in signal.bits().iter().enumerate() {
+                        outputs.push((signal.bit_name(offset), *f));
+                    }
+                }
+                ports.push(Port {
+                    name: p.name.clone(),
+                    output,
+                    range: signal.range,
+                });
+            }
+            synthesize(
+                &mut ctx,
+                &t.name,
+                &ports,
+                &outputs,
+                dont_care,
+                path,
+                &settings.options,
+            )
         }
         Err(e) => {
-            println!("Error: {:?}", e);
+            print!("{}", Diagnostic::from(e).render(expr));
             println!("----------------------------------------------");
             false
         }
     }
 }
 
+/* a PLA label as a plain Verilog identifier, e.g. "a<0>" becomes "a_0_" */
+fn pla_identifier(label: &str) -> String {
+    let mut name: String = label
+        .chars()
+        .map(|c| match c {
+            '_' | '$' => c,
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '_',
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/* the labels after `key`, ".ilb" or ".ob", when there are `count` distinct
+ * ones; "v[0] v[1] ..." stay the bits of the vector v. Otherwise the bits
+ * of the vector `fallback` */
+fn pla_labels(text: &str, key: &str, count: usize, fallback: &str) -> Vec<String> {
+    let labels: Vec<&str> = text
+        .lines()
+        .find_map(|l| l.trim().strip_prefix(key).filter(|r| r.starts_with(' ')))
+        .map(|r| r.split_whitespace().collect())
+        .unwrap_or_default();
+    let vector = labels
+        .first()
+        .and_then(|l| l.split_once('['))
+        .map(|(v, _)| v);
+    if let Some(v) = vector.filter(|v| pla_identifier(v) == *v) {
+        let bits = (0..labels.len()).all(|i| labels[i] == format!("{}[{}]", v, i));
+        if labels.len() == count && bits {
+            return labels.iter().map(|l| l.to_string()).collect();
+        }
+    }
+
+    let labels: Vec<String> = labels.iter().map(|l| pla_identifier(l)).collect();
+    let distinct = labels
+        .iter()
+        .enumerate()
+        .all(|(i, l)| !labels[..i].contains(l));
+    if labels.len() == count && distinct {
+        labels
+    } else if count == 1 {
+        vec![String::from(fallback)]
+    } else {
+        (0..count).map(|i| format!("{}[{}]", fallback, i)).collect()
+    }
+}
+
+/* the on-set and don't-care set of every output of a PLA that Espresso has
+ * read, over the inputs `names`; the sets its .type leaves out are what the
+ * given ones do not cover, as in Espresso's read_pla */
+fn pla_sets(
+    text: &str,
+    names: &[String],
+    outputs: usize,
+    m: &mut bdd::Manager,
+) -> (Vec<bdd::Bdd>, Vec<bdd::Bdd>) {
+    let kind = text
+        .lines()
+        .find_map(|l| l.trim().strip_prefix(".type "))
+        .map(|t| t.trim())
+        .unwrap_or("fd");
+    let vars: Vec<bdd::Bdd> = names.iter().map(|n| m.var(n)).collect();
+    /* F, D and R as written, one function per output */
+    let mut sets = [
+        vec![bdd::Bdd::FALSE; outputs],
+        vec![bdd::Bdd::FALSE; outputs],
+        vec![bdd::Bdd::FALSE; outputs],
+    ];
+    for line in text.lines().map(|l| l.trim()) {
+        if line == ".e" || line == ".end" {
+            break;
+        }
+        if line.is_empty() || line.starts_with('.') || line.starts_with('#') {
+            continue;
+        }
+        let row: Vec<char> = line.chars().filter(|c| !" \t|".contains(*c)).collect();
+        if row.len() < names.len() + outputs {
+            continue;
+        }
+        let mut term = bdd::Bdd::TRUE;
+        for (c, var) in row.iter().zip(vars.iter()) {
+            term = match c {
+                '0' => m.and(term, !*var),
+                '1' => m.and(term, *var),
+                '-' | '2' => term,
+                _ => bdd::Bdd::FALSE,
+            };
+        }
+        for (k, c) in row[names.len()..names.len() + outputs].iter().enumerate() {
+            let set = match c {
+                '1' | '4' if kind.contains('f') => 0,
+                '-' | '2' if kind.contains('d') => 1,
+                '0' | '3' if kind.contains('r') => 2,
+                _ => continue,
+            };
+            sets[set][k] = m.or(sets[set][k], term);
+        }
+    }
+
+    let [on, dc, off] = sets;
+    let mut fs: Vec<bdd::Bdd> = Vec::new();
+    let mut dcs: Vec<bdd::Bdd> = Vec::new();
+    for k in 0..outputs {
+        match (kind.contains('f'), kind.contains('d'), kind.contains('r')) {
+            (false, _, _) => {
+                let given = m.or(dc[k], off[k]);
+                fs.push(!given);
+                dcs.push(dc[k]);
+            }
+            (true, false, true) => {
+                let given = m.or(on[k], off[k]);
+                fs.push(on[k]);
+                dcs.push(!given);
+            }
+            _ => {
+                fs.push(on[k]);
+                dcs.push(dc[k]);
+            }
+        }
+    }
+    (fs, dcs)
+}
+
+/* minimizes a Berkeley PLA file and maps it like an expression, its .ilb
+ * and .ob labels naming the ports; without them the ports are the vectors
+ * "in" and "out" */
+fn parser_pla_in(file: &str, path: Option<&str>, settings: &Settings) -> bool {
+    println!("PLA: {}", file);
+    let text = match std::fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("error: cannot read {}: {}", file, e);
+            println!("----------------------------------------------");
+            return false;
+        }
+    };
+    let cover = match espresso_minimizer_from_path(file, &settings.options) {
+        Ok(cover) => cover,
+        Err(e) => {
+            println!("error: {}", e);
+            println!("----------------------------------------------");
+            return false;
+        }
+    };
+    let inputs = pla_labels(&text, ".ilb", cover.inputs, "in");
+    let outputs = pla_labels(&text, ".ob", cover.outputs, "out");
+    print_cover(&cover, &inputs);
+
+    /* one port per label, or one vector port */
+    let mut ports: Vec<Port> = Vec::new();
+    for (names, count, output) in [
+        (&inputs, cover.inputs, false),
+        (&outputs, cover.outputs, true),
+    ] {
+        if names.first().is_some_and(|n| n.ends_with(']')) {
+            let name = names[0].split('[').next().unwrap();
+            ports.push(Port {
+                name: name.to_string(),
+                output,
+                range: Some((count - 1, 0)),
+            });
+        } else {
+            ports.extend(names.iter().map(|n| Port {
+                name: n.clone(),
+                output,
+                range: None,
+            }));
+        }
+    }
+
+    let module = std::path::Path::new(file)
+        .file_stem()
+        .map(|s| pla_identifier(&s.to_string_lossy()))
+        .unwrap_or_else(|| String::from("pla"));
+    let functions: Vec<(String, Function)> = outputs
+        .iter()
+        .cloned()
+        .zip(sum_of_products(&cover))
+        .collect();
+    let netlist = print_netlist(&module, &ports, &inputs, &functions, path);
+
+    /* the netlist against the PLA as written, not the minimized cover */
+    let mut ctx = eval::Context::new();
+    let (fs, dcs) = pla_sets(&text, &inputs, cover.outputs, ctx.manager());
+    let sources: Vec<(String, bdd::Bdd)> = outputs.into_iter().zip(fs.clone()).collect();
+    let equivalent = check_equivalence(&mut ctx, &netlist, &sources, &fs, &dcs);
+    println!("----------------------------------------------");
+    equivalent
+}
+
+/* writes the PLA of a free-standing expression to `file`, or prints it;
+ * x/z bits and the --dont-care condition make up its don't-care rows */
+fn parser_pla_out(expr: &str, file: Option<&str>, settings: &Settings) -> bool {
+    let t = match verilog::ExprParser::new().parse(expr) {
+        Ok(t) => t,
+        Err(e) => {
+            print!("{}", Diagnostic::from(e).render(expr));
+            return false;
+        }
+    };
+    let mut ctx = eval::Context::new();
+    let bits = match ctx.declare_inputs(&t).and_then(|_| ctx.eval(&t)) {
+        Ok(bits) => bits,
+        Err(e) => {
+            print!("{}", e.render(expr));
+            return false;
+        }
+    };
+    let dont_care = match dont_care(&mut ctx, settings, true) {
+        Some(c) => c,
+        None => return false,
+    };
+
+    let outputs = expression_outputs(&bits);
+    let (fs, dcs) = care_sets(&mut ctx, &outputs, dont_care);
+    let (on_set, dc_set, item_name) = ctx.create_cover(&fs, &dcs);
+    let names: Vec<String> = outputs.into_iter().map(|(target, _)| target).collect();
+    let mut pla = on_set
+        .to_labeled_pla(&dc_set, &item_name, &names)
+        .join("\n");
+    pla.push('\n');
+    match file {
+        Some(file) => match std::fs::write(file, pla) {
+            Ok(_) => true,
+            Err(e) => {
+                println!("error: cannot write {}: {}", file, e);
+                false
+            }
+        },
+        None => {
+            print!("{}", pla);
+            true
+        }
+    }
+}
+
 fn parser_help() {
-    println!("Format: parser [type] [expr] [path-to-lib file]");
+    println!("Format: parser [type] [expr] [path-to-lib file] [options]");
+    println!("        parser pla-in [pla file] [path-to-lib file] [options]");
+    println!("        parser pla-out [expr] [pla file] [options]");
     println!("    [type]: expr, module, test");
     println!("    [expr]: \"~a\"");
+    println!("    [options]: --exact (minimum cubes), --exact-literals (minimum literals),");
+    println!("               --dont-care [expr] (outputs are free where [expr] holds),");
+    println!("               --phase (realize an output inverted when that is cheaper),");
+    println!("               --pair (decode pairs of inputs together when that is cheaper)");
     println!("example:");
     println!("parser expr (1'b1&v)|(~u&(&m| |start)&t) ./library.json");
+    println!("parser module \"module m(input a, b, output y); assign y = a & b; endmodule\" ./library.json");
     println!("parser test ./library.json");
+    println!("parser expr \"a&b | a&c | b&c\" ./library.json --exact");
+    println!("parser expr \"valid & a | b\" ./library.json --dont-care \"!valid\"");
+    println!("parser expr \"a | b | c\" ./library.json --phase");
+    println!("parser expr \"(a ^ b) & c | (a ^ ~b) & d\" ./library.json --pair");
+    println!("parser pla-in adder.pla ./library.json");
+    println!("parser pla-out \"a & b | c\" out.pla");
 }
 
-fn parser_test(path: &str) {
-    assert!(parser_exp("(1'b1&v)|(~u&(&m| |start)&t)", Some(path)));
-    assert!(!parser_exp("001", Some(path)));
-    assert!(parser_exp("100", Some(path)));
-    assert!(parser_exp("1'b01", Some(path)));
-    assert!(!parser_exp("1'b2", Some(path)));
-    assert!(parser_exp("2'hff", Some(path)));
-    assert!(parser_exp("2'hf", Some(path)));
-    assert!(parser_exp("1'h2", Some(path)));
-    assert!(parser_exp("1'o7", Some(path)));
-    assert!(!parser_exp("1'o8", Some(path)));
-    assert!(!parser_exp("2'b", Some(path)));
-    assert!(parser_exp("'b101", Some(path)));
-    assert!(parser_exp("a|||b", Some(path)));
-    assert!(parser_exp("a|| |b", Some(path)));
-    assert!(!parser_exp("||a || |b", Some(path)));
+fn parser_test(path: &str, settings: &Settings) {
+    assert!(parser_exp(
+        "(1'b1&v)|(~u&(&m| |start)&t)",
+        Some(path),
+        settings
+    ));
+    assert!(!parser_exp("001", Some(path), settings));
+    assert!(parser_exp("100", Some(path), settings));
+    assert!(parser_exp("1'b01", Some(path), settings));
+    assert!(!parser_exp("1'b2", Some(path), settings));
+    assert!(parser_exp("2'hff", Some(path), settings));
+    assert!(parser_exp("2'hf", Some(path), settings));
+    assert!(parser_exp("1'h2", Some(path), settings));
+    assert!(parser_exp("1'o7", Some(path), settings));
+    assert!(!parser_exp("1'o8", Some(path), settings));
+    assert!(!parser_exp("2'b", Some(path), settings));
+    assert!(parser_exp("'b101", Some(path), settings));
+    assert!(parser_exp("a|||b", Some(path), settings));
+    assert!(parser_exp("a|| |b", Some(path), settings));
+    assert!(!parser_exp("||a || |b", Some(path), settings));
+    assert!(parser_module(
+        "module mod(input [1:0] in, output out); assign out = in[0]; endmodule",
+        Some(path),
+        settings
+    ));
+    assert!(parser_module(
+        "module mod(a, b, y); input [3:0] a, b; output y; wire t; assign y = t | b[0]; assign t = &a; endmodule",
+        Some(path),
+        settings
+    ));
+    assert!(!parser_module(
+        "module mod(a, y); input a; assign y = a; endmodule",
+        Some(path),
+        settings
+    ));
+    assert!(!parser_module(
+        "module mod(input a, output y); wire t; assign t = y & a; assign y = t; endmodule",
+        Some(path),
+        settings
+    ));
     assert!(parser_module(
-        "module mod(input [1:0] in, output out) { assign out = a[0]; }"
+        "module mux(input s, a, output y); assign y = s ? a : 1'bx; endmodule",
+        Some(path),
+        settings
+    ));
+    assert!(parser_exp("4'b1x0z & a", Some(path), settings));
+    let phase = Settings {
+        options: Options {
+            phase: true,
+            ..settings.options
+        },
+        dont_care: None,
+    };
+    assert!(parser_exp("a | b | c", Some(path), &phase));
+    let pair = Settings {
+        options: Options {
+            pair: true,
+            ..settings.options
+        },
+        dont_care: None,
+    };
+    assert!(parser_exp("(a ^ b) & c | (a ^ ~b) & d", Some(path), &pair));
+
+    /* a PLA written out reads back in under its labels */
+    let pla = env::temp_dir().join("parser_test.pla");
+    let pla = pla.to_str().unwrap();
+    assert!(parser_pla_out("{a & b, c | 2'bx1}", Some(pla), settings));
+    assert!(parser_pla_in(pla, Some(path), settings));
+    std::fs::remove_file(pla).unwrap();
+    assert!(!parser_pla_in(
+        "/nonexistent/input.pla",
+        Some(path),
+        settings
     ));
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    /* options may appear anywhere, the rest is positional */
+    let mut settings = Settings::default();
+    let mut args: Vec<String> = Vec::new();
+    let mut iter = env::args();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--exact" => settings.options.mode = Mode::Exact,
+            "--exact-literals" => settings.options.mode = Mode::ExactLiterals,
+            "--phase" => settings.options.phase = true,
+            "--pair" => settings.options.pair = true,
+            "--dont-care" => match iter.next() {
+                Some(condition) => settings.dont_care = Some(condition),
+                None => return parser_help(),
+            },
+            _ => args.push(arg),
+        }
+    }
     match args.len() {
         4 => {
             let type_here = &args[1];
             let expr = &args[2];
             match type_here.trim().to_lowercase().as_str() {
-                "expr" => parser_exp(expr, Some(args[3].as_str())),
-                "module" => parser_module(expr),
+                "expr" => parser_exp(expr, Some(args[3].as_str()), &settings),
+                "module" => parser_module(expr, Some(args[3].as_str()), &settings),
+                "pla-in" => parser_pla_in(expr, Some(args[3].as_str()), &settings),
+                "pla-out" => parser_pla_out(expr, Some(args[3].as_str()), &settings),
                 _ => {
                     parser_help();
                     false
@@ -179,10 +829,12 @@ fn main() {
             let type_here = &args[1];
             let expr = &args[2];
             match type_here.trim().to_lowercase().as_str() {
-                "expr" => parser_exp(expr, Some("./library.json")),
-                "module" => parser_module(expr),
+                "expr" => parser_exp(expr, Some("./library.json"), &settings),
+                "module" => parser_module(expr, Some("./library.json"), &settings),
+                "pla-in" => parser_pla_in(expr, Some("./library.json"), &settings),
+                "pla-out" => parser_pla_out(expr, None, &settings),
                 "test" => {
-                    parser_test(expr.as_str());
+                    parser_test(expr.as_str(), &settings);
                     true
                 }
                 _ => {
@@ -194,7 +846,7 @@ fn main() {
         2 => {
             let type_here = &args[1];
             if type_here.trim().to_lowercase().as_str() == "test" {
-                parser_test("./library.json");
+                parser_test("./library.json", &settings);
             } else {
                 parser_help();
             }
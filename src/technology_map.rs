@@ -1,7 +1,11 @@
+use crate::bdd::{Bdd, Manager};
+use daggy::petgraph::algo::toposort;
 use daggy::petgraph::visit::IntoNodeReferences;
 use daggy::{Dag, NodeIndex, Walker};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs::File};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
 
 #[derive(Serialize, Deserialize)]
 struct Node {
@@ -25,7 +29,6 @@ struct GateLibrary {
 #[derive(Debug)]
 struct DAGWithInfo {
     input: u32,
-    output: u32,
     dag: Dag<Gate, u32>,
     cost: f32,
     input_nodes: Vec<NodeIndex>,
@@ -53,7 +56,6 @@ impl DAGWithInfo {
 
         DAGWithInfo {
             input: input_nodes.len() as u32,
-            output: output_nodes.len() as u32,
             dag,
             cost: cost as f32 / input_nodes.len() as f32,
             input_nodes,
@@ -70,6 +72,73 @@ enum Gate {
     Input(String),
     Nand,
     Nor,
+    Output(String),
+}
+
+/// Port of the generated netlist module; `range` is `[msb:lsb]` for vectors.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Port {
+    pub name: String,
+    pub output: bool,
+    pub range: Option<(usize, usize)>,
+}
+
+impl Port {
+    fn declaration(&self) -> String {
+        let direction = if self.output { "output" } else { "input" };
+        match self.range {
+            None => format!("{} {}", direction, self.name),
+            Some((msb, lsb)) => format!("{} [{}:{}] {}", direction, msb, lsb, self.name),
+        }
+    }
+}
+
+/// Literal of a product term: input `i` as `Input(i, true)`, its complement
+/// as `Input(i, false)`, or a decoder line, the sum of two such literals.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Literal {
+    Input(usize, bool),
+    Decoder((usize, bool), (usize, bool)),
+}
+
+/// Output bit to be mapped: a constant, or a sum of products over the
+/// inputs that the output is the complement of when `negative`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Function {
+    Constant(bool),
+    SumOfProducts {
+        terms: Vec<Vec<Literal>>,
+        negative: bool,
+    },
+}
+
+impl Function {
+    /// The bit as text over the input names `inputs`, "f = <a><b'> + <c>"
+    /// with a decoder line as "<a|b'>", or "f' = ..." when negative.
+    pub fn format(&self, inputs: &[String]) -> String {
+        let literal =
+            |(i, value): (usize, bool)| format!("{}{}", inputs[i], if value { "" } else { "'" });
+        match self {
+            Function::Constant(value) => format!("f = 1'b{}", *value as u8),
+            Function::SumOfProducts { terms, negative } => {
+                let terms: Vec<String> = terms
+                    .iter()
+                    .map(|term| {
+                        term.iter()
+                            .map(|l| match *l {
+                                Literal::Input(i, value) => format!("<{}>", literal((i, value))),
+                                Literal::Decoder(a, b) => {
+                                    format!("<{}|{}>", literal(a), literal(b))
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let f = if *negative { "f'" } else { "f" };
+                format!("{} = {}", f, terms.join(" + "))
+            }
+        }
+    }
 }
 
 fn replace_node_by_graph(src: &[DAGWithInfo], target: &mut Dag<Gate, u32>, target_node: NodeIndex) {
@@ -78,7 +147,14 @@ fn replace_node_by_graph(src: &[DAGWithInfo], target: &mut Dag<Gate, u32>, targe
         .iter(target)
         .map(|(_, n)| n)
         .collect();
-    let output = target.children(target_node).iter(target).next();
+    /* consumers that are already mapped: pattern inputs of the gates this
+     * one feeds, or output ports */
+    let outputs: Vec<NodeIndex> = target
+        .children(target_node)
+        .iter(target)
+        .map(|(_, n)| n)
+        .filter(|n| !matches!(target[*n], Gate::And | Gate::Or | Gate::Not))
+        .collect();
     let mut stack: Vec<NodeIndex> = Vec::new();
     stack.clone_from(&input_list);
 
@@ -115,10 +191,8 @@ fn replace_node_by_graph(src: &[DAGWithInfo], target: &mut Dag<Gate, u32>, targe
                     != 0
                 {
                     for (_, n) in sub_pattern.dag.parents(*idx_sub).iter(&sub_pattern.dag) {
-                        if repeat_node.contains_key(&n) {
-                            target
-                                .add_edge(*repeat_node.get(&n).unwrap(), *idx_new, 1)
-                                .unwrap();
+                        if let Some(idx) = repeat_node.get(&n) {
+                            target.add_edge(*idx, *idx_new, 1).unwrap();
                         } else {
                             let idx =
                                 target.add_node(sub_pattern.dag.node_weight(n).unwrap().clone());
@@ -147,8 +221,10 @@ fn replace_node_by_graph(src: &[DAGWithInfo], target: &mut Dag<Gate, u32>, targe
         }
     }
 
-    if let Some((_, n)) = output {
-        target.add_edge(stack.pop().unwrap(), n, 1).unwrap();
+    if let Some(root) = stack.pop() {
+        for n in outputs {
+            target.add_edge(root, n, 1).unwrap();
+        }
     }
 }
 
@@ -193,10 +269,10 @@ fn straightforward_map(path: &str, mut origin: Dag<Gate, u32>) -> Dag<Gate, u32>
     not_lib.sort_by(|a, b| a.cost.partial_cmp(&b.cost).unwrap());
     or_lib.sort_by(|a, b| a.cost.partial_cmp(&b.cost).unwrap());
 
-    let target = origin.clone();
-
-    for (index, n) in target.node_references() {
-        match n {
+    /* consumers first, so a gate with fan-out sees every pattern it feeds */
+    let order = toposort(origin.graph(), None).unwrap();
+    for index in order.into_iter().rev() {
+        match origin[index] {
             Gate::And => replace_node_by_graph(&and_lib, &mut origin, index),
             Gate::Not => replace_node_by_graph(&not_lib, &mut origin, index),
             Gate::Or => replace_node_by_graph(&or_lib, &mut origin, index),
@@ -215,42 +291,118 @@ fn straightforward_map(path: &str, mut origin: Dag<Gate, u32>) -> Dag<Gate, u32>
     )
 }
 
-fn transform_boolean_algebra_to_dag(boolean_function: String) -> Dag<Gate, u32> {
-    let (_, last) = boolean_function.split_at(boolean_function.find('=').unwrap() + 1);
-    let or_level: Vec<&str> = last.split(&['+'][..]).map(|f| f.trim()).collect();
-    let mut and_level: Vec<Vec<String>> = Vec::new();
+/* `functions` pairs an output bit with what drives it; the literals index
+ * `inputs`, and an input node is named after its entry there verbatim.
+ * A negative output is the inverted sum of products, or with `de_morgan`
+ * the product of its terms' complements, each a sum of inverted literals */
+fn transform_boolean_algebra_to_dag(
+    inputs: &[String],
+    functions: &[(String, Function)],
+    de_morgan: bool,
+) -> Dag<Gate, u32> {
     let mut dag: Dag<Gate, u32> = Dag::new();
-    let mut input_nodes: HashMap<String, NodeIndex> = HashMap::new();
-
-    for v in or_level.iter() {
-        let list: Vec<String> = v
-            .split(&['<', '>'][..])
-            .filter(|f| !f.trim().is_empty())
-            .map(|f| String::from(f.trim()))
-            .collect();
-        and_level.push(list);
-    }
+    let mut input_nodes: HashMap<usize, NodeIndex> = HashMap::new();
+    /* one inverter per input and one AND per distinct product term, shared
+     * by every output that uses them */
+    let mut not_nodes: HashMap<usize, NodeIndex> = HashMap::new();
+    let mut and_nodes: HashMap<Vec<Literal>, NodeIndex> = HashMap::new();
+    let mut decoder_nodes: HashMap<Literal, NodeIndex> = HashMap::new();
+    let mut complement_nodes: HashMap<Vec<Literal>, NodeIndex> = HashMap::new();
+
+    /* the node of input `i`, or of its complement */
+    let mut literal = |dag: &mut Dag<Gate, u32>, (i, value): (usize, bool)| -> NodeIndex {
+        let input = *input_nodes
+            .entry(i)
+            .or_insert_with(|| dag.add_node(Gate::Input(inputs[i].clone())));
+        if value {
+            return input;
+        }
+        *not_nodes.entry(i).or_insert_with(|| {
+            let n = dag.add_node(Gate::Not);
+            dag.add_edge(input, n, 1).unwrap();
+            n
+        })
+    };
+
+    for (target, function) in functions.iter() {
+        let mut output = dag.add_node(Gate::Output(target.clone()));
+
+        let (terms, negative) = match function {
+            /* constant outputs are driven by a pseudo input named after the literal */
+            Function::Constant(value) => {
+                let constant = dag.add_node(Gate::Input(format!("1'b{}", *value as u8)));
+                dag.add_edge(constant, output, 1).unwrap();
+                continue;
+            }
+            Function::SumOfProducts { terms, negative } => (terms, *negative),
+        };
+
+        if negative && de_morgan {
+            let and_gate = dag.add_node(Gate::And);
+            dag.add_edge(and_gate, output, 1).unwrap();
+            for v in terms.iter() {
+                if let Some(or_gate) = complement_nodes.get(v) {
+                    dag.add_edge(*or_gate, and_gate, 1).unwrap();
+                    continue;
+                }
+                let or_gate = dag.add_node(Gate::Or);
+                complement_nodes.insert(v.clone(), or_gate);
+                dag.add_edge(or_gate, and_gate, 1).unwrap();
+                for l in v.iter() {
+                    let node = match *l {
+                        Literal::Input(i, value) => literal(&mut dag, (i, !value)),
+                        /* the complement of a decoder line is a product */
+                        Literal::Decoder((a, u), (b, w)) => {
+                            let n = dag.add_node(Gate::And);
+                            let a = literal(&mut dag, (a, !u));
+                            let b = literal(&mut dag, (b, !w));
+                            dag.add_edge(a, n, 1).unwrap();
+                            dag.add_edge(b, n, 1).unwrap();
+                            n
+                        }
+                    };
+                    dag.add_edge(node, or_gate, 1).unwrap();
+                }
+            }
+            continue;
+        }
+
+        /* an output in negative phase gets the final inverter */
+        if negative {
+            let not_gate = dag.add_node(Gate::Not);
+            dag.add_edge(not_gate, output, 1).unwrap();
+            output = not_gate;
+        }
 
-    let or_gate = dag.add_node(Gate::Or);
-
-    for v in and_level.iter() {
-        let and_gate = dag.add_node(Gate::And);
-        dag.add_edge(and_gate, or_gate, 1).unwrap();
-        for i in v.iter() {
-            if i.find('\'').is_some() {
-                let not_gate = dag.add_node(Gate::Not);
-                let input = input_nodes
-                    .entry(i.trim_matches('\'').to_string())
-                    .or_insert(dag.add_node(Gate::Input(i.trim_matches('\'').to_string())));
-                dag.add_edge(not_gate, and_gate, 1).unwrap();
-                dag.add_edge(*input, not_gate, 1).unwrap();
-            } else if input_nodes.contains_key(i) {
-                dag.add_edge(*input_nodes.get(i).unwrap(), and_gate, 1)
-                    .unwrap();
-            } else {
-                let input = dag.add_node(Gate::Input(i.to_string()));
-                input_nodes.insert(i.to_string(), input);
-                dag.add_edge(input, and_gate, 1).unwrap();
+        let or_gate = dag.add_node(Gate::Or);
+        dag.add_edge(or_gate, output, 1).unwrap();
+
+        for v in terms.iter() {
+            if let Some(and_gate) = and_nodes.get(v) {
+                dag.add_edge(*and_gate, or_gate, 1).unwrap();
+                continue;
+            }
+            let and_gate = dag.add_node(Gate::And);
+            and_nodes.insert(v.clone(), and_gate);
+            dag.add_edge(and_gate, or_gate, 1).unwrap();
+            for l in v.iter() {
+                let node = match *l {
+                    Literal::Input(i, value) => literal(&mut dag, (i, value)),
+                    /* one decoder line per sum, shared like the products */
+                    Literal::Decoder(a, b) => match decoder_nodes.get(l) {
+                        Some(n) => *n,
+                        None => {
+                            let n = dag.add_node(Gate::Or);
+                            let a = literal(&mut dag, a);
+                            let b = literal(&mut dag, b);
+                            dag.add_edge(a, n, 1).unwrap();
+                            dag.add_edge(b, n, 1).unwrap();
+                            decoder_nodes.insert(*l, n);
+                            n
+                        }
+                    },
+                };
+                dag.add_edge(node, and_gate, 1).unwrap();
             }
         }
     }
@@ -258,78 +410,140 @@ fn transform_boolean_algebra_to_dag(boolean_function: String) -> Dag<Gate, u32>
     dag
 }
 
-fn generate_netlist(dag: Dag<Gate, u32>) -> String {
-    let dag_info = DAGWithInfo::new(dag);
-    let out_name = "out";
-    let mut result = String::from("module test(");
+/* nearest gate or primary input at or above `node`, skipping pattern placeholders */
+fn driver(dag: &Dag<Gate, u32>, mut node: NodeIndex) -> NodeIndex {
+    loop {
+        if dag[node] == Gate::Nor || dag[node] == Gate::Nand {
+            return node;
+        }
+        match dag.parents(node).iter(dag).next() {
+            Some((_, n)) => node = n,
+            None => return node,
+        }
+    }
+}
+
+/* the driver `node` inverts, when it is a NAND or NOR with one driver on all inputs */
+fn inverted(dag: &Dag<Gate, u32>, node: NodeIndex) -> Option<NodeIndex> {
+    if !matches!(dag[node], Gate::Nand | Gate::Nor) {
+        return None;
+    }
+    let mut drivers = dag.parents(node).iter(dag).map(|(_, p)| driver(dag, p));
+    let first = drivers.next()?;
+    drivers.all(|d| d == first).then_some(first)
+}
+
+/* the patterns of AND and OR end in an inverter, so one feeding a NOT leaves
+ * two in a row; their consumers read the signal in front of both instead,
+ * and gates that drive nothing anymore are dropped */
+fn cancel_double_inversions(mut dag: Dag<Gate, u32>) -> Dag<Gate, u32> {
+    let source = |dag: &Dag<Gate, u32>, mut node: NodeIndex| {
+        while let Some(n) = inverted(dag, node).and_then(|n| inverted(dag, n)) {
+            node = n;
+        }
+        node
+    };
+    let moves: Vec<(NodeIndex, NodeIndex, NodeIndex)> = dag
+        .raw_edges()
+        .iter()
+        .filter_map(|e| {
+            let from = driver(&dag, e.source());
+            let to = source(&dag, from);
+            (to != from).then_some((e.source(), e.target(), to))
+        })
+        .collect();
+    for (parent, child, to) in moves {
+        if let Some(e) = dag.find_edge(parent, child) {
+            dag.remove_edge(e);
+        }
+        dag.add_edge(to, child, 1).unwrap();
+    }
+
+    let mut stack: Vec<NodeIndex> = dag
+        .node_references()
+        .filter(|(_, n)| matches!(n, Gate::Output(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut used: HashSet<NodeIndex> = stack.iter().copied().collect();
+    while let Some(n) = stack.pop() {
+        for (_, p) in dag.parents(n).iter(&dag) {
+            if used.insert(p) {
+                stack.push(p);
+            }
+        }
+    }
+    dag.filter_map(|i, n| used.contains(&i).then(|| n.clone()), |_, e| Some(*e))
+}
+
+fn gate_count(dag: &Dag<Gate, u32>) -> usize {
+    dag.node_references()
+        .filter(|(_, n)| matches!(n, Gate::Nand | Gate::Nor))
+        .count()
+}
+
+fn generate_netlist(dag: &Dag<Gate, u32>, module: &str, ports: &[Port]) -> String {
+    let dag_info = DAGWithInfo::new(dag.clone());
+    let dag = &dag_info.dag;
     let mut name_pool: HashMap<NodeIndex, String> = HashMap::new();
+    let mut wires: Vec<String> = Vec::new();
 
     /* module interface definition */
+    let declarations: Vec<String> = ports.iter().map(|p| p.declaration()).collect();
+    let mut result = format!("module {}({});\n", module, declarations.join(", "));
+
     for i in dag_info.input_nodes.iter() {
-        if let Gate::Input(s) = dag_info.dag.node_weight(*i).unwrap() {
-            result.push_str("input ");
-            result.push_str(s.trim_matches('\''));
-            result.push_str(", ");
-            name_pool.insert(*i, s.trim_matches('\'').to_string());
+        if let Gate::Input(s) = dag.node_weight(*i).unwrap() {
+            name_pool.insert(*i, s.clone());
         }
     }
 
-    result.push_str(format!("output {out_name});\n").as_str());
-
     /* wire name generator */
-    let namer = || unsafe {
-        static mut ID: usize = 0;
-        ID += 1;
-        ID
-    };
-
-    let gate_namer = || unsafe {
-        static mut ID: usize = 0;
-        ID += 1;
-        ID
-    };
+    let mut wire_id: usize = 0;
+    let mut gate_id: usize = 0;
 
+    /* outputs driven by an input, a constant or another output become assigns */
+    let mut assigns: String = String::new();
     let mut parent_stack: Vec<NodeIndex> = Vec::new();
-    parent_stack.push(*dag_info.output_nodes.first().unwrap());
-    name_pool.insert(
-        *dag_info.output_nodes.first().unwrap(),
-        out_name.to_string(),
-    );
+    for &o in dag_info.output_nodes.iter() {
+        if let Gate::Output(target) = &dag[o] {
+            let source = driver(dag, o);
+            match name_pool.get(&source) {
+                Some(name) => assigns += &format!("assign {} = {};\n", target, name),
+                None => {
+                    name_pool.insert(source, target.clone());
+                    parent_stack.push(source);
+                }
+            }
+        }
+    }
 
+    let mut visited: Vec<NodeIndex> = Vec::new();
     let mut gates_list: String = String::new();
     while !parent_stack.is_empty() {
         let mut all_child: Vec<NodeIndex> = Vec::new();
-        all_child.clear();
         for &n in parent_stack.iter() {
-            let mut gate_name = match dag_info.dag.node_weight(n).unwrap() {
-                Gate::Nor => format!("NOR g{}(", gate_namer()),
-                Gate::Nand => format!("NAND g{}(", gate_namer()),
+            if visited.contains(&n) {
+                continue;
+            }
+            visited.push(n);
+            gate_id += 1;
+            let mut gate_name = match dag.node_weight(n).unwrap() {
+                Gate::Nor => format!("NOR g{}(", gate_id),
+                Gate::Nand => format!("NAND g{}(", gate_id),
                 _ => panic!("It should not be here"),
             };
 
-            for (_, n) in dag_info.dag.parents(n).iter(&dag_info.dag) {
-                let mut parent_recursion = dag_info
-                    .dag
-                    .recursive_walk(n, |g, idx| g.parents(idx).iter(g).next());
-
-                while let Some((_, node)) = parent_recursion.walk_next(&dag_info.dag) {
-                    if dag_info.dag[node] == Gate::Nor
-                        || dag_info.dag[node] == Gate::Nand
-                        || name_pool.contains_key(&node)
-                    {
-                        if let Gate::Input(input_var) = dag_info.dag.node_weight(node).unwrap() {
-                            gate_name.push_str(input_var.trim_matches('\''));
-                            gate_name.push_str(", ");
-                        } else {
-                            let value = name_pool.entry(node).or_insert(format!("t{}", namer()));
-                            gate_name.push_str(value.as_str());
-                            gate_name.push_str(", ");
-                            if !all_child.contains(&node) {
-                                all_child.push(node);
-                            }
-                        }
-                        break;
-                    }
+            for (_, p) in dag.parents(n).iter(dag) {
+                let node = driver(dag, p);
+                let value = name_pool.entry(node).or_insert_with(|| {
+                    wire_id += 1;
+                    wires.push(format!("t{}", wire_id));
+                    format!("t{}", wire_id)
+                });
+                gate_name.push_str(value.as_str());
+                gate_name.push_str(", ");
+                if matches!(dag[node], Gate::Nor | Gate::Nand) && !all_child.contains(&node) {
+                    all_child.push(node);
                 }
             }
             gate_name.push_str(name_pool.get(&n).unwrap().as_str());
@@ -341,36 +555,319 @@ fn generate_netlist(dag: Dag<Gate, u32>) -> String {
         parent_stack.clone_from(&all_child);
     }
 
-    for (k, v) in name_pool {
-        if (dag_info.dag[k] == Gate::Nand || dag_info.dag[k] == Gate::Nor)
-            && (v.as_bytes()[0] as char == 't')
-        {
-            result += &format!("wire {};\n", v);
-        }
+    for v in wires.iter() {
+        result += &format!("wire {};\n", v);
     }
 
     result += &gates_list;
+    result += &assigns;
     result.push_str("endmodule");
 
     result
 }
 
-pub fn technology_map_by_nand_nor(boolean_function: String, path: &str) -> String {
-    let dag = transform_boolean_algebra_to_dag(boolean_function);
+/// Mapped module: the Verilog text, and the gates it was written from.
+pub struct Netlist {
+    pub verilog: String,
+    dag: Dag<Gate, u32>,
+}
+
+impl Netlist {
+    /// Rebuilds the function of every output bit from the NAND/NOR gates,
+    /// in `manager`, where a primary input is the variable of its name.
+    pub fn functions(&self, manager: &mut Manager) -> Vec<(String, Bdd)> {
+        let dag = &self.dag;
+        let mut value: HashMap<NodeIndex, Bdd> = HashMap::new();
+        let mut outputs: Vec<(String, Bdd)> = Vec::new();
+        for n in toposort(dag.graph(), None).unwrap() {
+            let parents: Vec<Bdd> = dag.parents(n).iter(dag).map(|(_, p)| value[&p]).collect();
+            let f = match &dag[n] {
+                Gate::Nand => !parents.iter().fold(Bdd::TRUE, |f, p| manager.and(f, *p)),
+                Gate::Nor => !parents.iter().fold(Bdd::FALSE, |f, p| manager.or(f, *p)),
+                /* a pattern input or an output follows its driver, as in
+                 * generate_netlist */
+                Gate::Input(_) | Gate::Output(_) if !parents.is_empty() => parents[0],
+                Gate::Input(name) => match name.as_str() {
+                    "1'b0" => Bdd::FALSE,
+                    "1'b1" => Bdd::TRUE,
+                    _ => manager.var(name),
+                },
+                gate => panic!("{:?} gate left after mapping", gate),
+            };
+            if let Gate::Output(target) = &dag[n] {
+                outputs.push((target.clone(), f));
+            }
+            value.insert(n, f);
+        }
+        outputs
+    }
+}
+
+impl Display for Netlist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.verilog)
+    }
+}
+
+/// Maps every `(output bit, function)` pair onto the NAND/NOR library at
+/// `path` and writes one netlist module with the given ports; the literals
+/// of the functions index `inputs`.
+pub fn technology_map_by_nand_nor(
+    module: &str,
+    ports: &[Port],
+    inputs: &[String],
+    functions: &[(String, Function)],
+    path: &str,
+) -> Netlist {
+    let map = |de_morgan: bool| {
+        let dag = transform_boolean_algebra_to_dag(inputs, functions, de_morgan);
+        cancel_double_inversions(straightforward_map(path, dag))
+    };
 
-    let lib = straightforward_map(path, dag);
+    /* a negative output may take fewer gates as a product of sums */
+    let mut lib = map(false);
+    let negative = functions
+        .iter()
+        .any(|(_, f)| matches!(f, Function::SumOfProducts { negative: true, .. }));
+    if negative {
+        let product_of_sums = map(true);
+        if gate_count(&product_of_sums) < gate_count(&lib) {
+            lib = product_of_sums;
+        }
+    }
 
     println!("lib: {:?}", lib);
-    if lib.node_count() == 1 {
-        let (_, node) = lib.node_references().next().unwrap();
-        if let Gate::Input(node_name) = node {
-            let result = format!(
-                "module test(input {}, output out);\nassign out = {};\nendmodule\n",
-                node_name, node_name
-            );
-            return result;
+
+    Netlist {
+        verilog: generate_netlist(&lib, module, ports),
+        dag: lib,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bdd::{Bdd, Manager};
+    use crate::technology_map::{
+        cancel_double_inversions, straightforward_map, technology_map_by_nand_nor,
+        transform_boolean_algebra_to_dag, Function, Literal, Netlist, Port,
+    };
+
+    fn port(name: &str, output: bool) -> Port {
+        Port {
+            name: name.to_string(),
+            output,
+            range: None,
         }
     }
 
-    generate_netlist(lib)
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn sum(terms: Vec<Vec<Literal>>, negative: bool) -> Function {
+        Function::SumOfProducts { terms, negative }
+    }
+
+    #[test]
+    fn test_shared_product_terms() {
+        let ports = [
+            port("a", false),
+            port("b", false),
+            port("c", false),
+            port("y", true),
+            port("z", true),
+        ];
+        let inputs = names(&["a", "b", "c"]);
+        let ab = vec![Literal::Input(0, true), Literal::Input(1, true)];
+        let functions = [
+            (
+                String::from("y"),
+                sum(vec![vec![Literal::Input(2, true)], ab.clone()], false),
+            ),
+            (
+                String::from("z"),
+                sum(vec![vec![Literal::Input(2, false)], ab], false),
+            ),
+        ];
+        let netlist =
+            technology_map_by_nand_nor("m", &ports, &inputs, &functions, "input/library.json")
+                .verilog;
+
+        /* a&b is built once and feeds both outputs, c is inverted once */
+        assert!(netlist.starts_with("module m(input a, input b, input c, output y, output z);\n"));
+        let and = netlist.matches("(a, b, ").count() + netlist.matches("(b, a, ").count();
+        assert_eq!(and, 1);
+        assert_eq!(netlist.matches("(c, c, ").count(), 1);
+    }
+
+    #[test]
+    fn test_negative_phase() {
+        let ports = [
+            port("a", false),
+            port("b", false),
+            port("c", false),
+            port("y", true),
+        ];
+        let inputs = names(&["a", "b", "c"]);
+        let l = |i: usize, value: bool| Literal::Input(i, value);
+        /* covers of one function in either phase, as Espresso returns them */
+        let covers = [
+            (
+                vec![vec![l(0, true)], vec![l(1, true)], vec![l(2, true)]],
+                vec![vec![l(0, false), l(1, false), l(2, false)]],
+            ),
+            (
+                vec![vec![l(0, false)], vec![l(1, false)]],
+                vec![vec![l(0, true), l(1, true)]],
+            ),
+            (
+                vec![vec![l(0, true), l(2, true)], vec![l(1, true), l(2, true)]],
+                vec![vec![l(0, false), l(1, false)], vec![l(2, false)]],
+            ),
+            (
+                vec![vec![l(0, true), l(1, false)], vec![l(0, true), l(2, false)]],
+                vec![vec![l(0, false)], vec![l(1, true), l(2, true)]],
+            ),
+        ];
+        let gates =
+            |netlist: &str| netlist.matches("NAND ").count() + netlist.matches("NOR ").count();
+        let mut m = Manager::new();
+        for (positive, negative) in covers {
+            let map = |terms: Vec<Vec<Literal>>, negative: bool| {
+                let functions = [(String::from("y"), sum(terms, negative))];
+                technology_map_by_nand_nor("m", &ports, &inputs, &functions, "input/library.json")
+            };
+            let (positive, negative) = (map(positive, false), map(negative, true));
+
+            /* the complement never takes more gates, and it drives y */
+            assert!(gates(&negative.verilog) <= gates(&positive.verilog));
+            assert!(negative.verilog.contains(", y);\n"));
+            assert_eq!(negative.functions(&mut m), positive.functions(&mut m));
+        }
+
+        /* y = (ab)' is one NAND, not a NAND and two inverters */
+        let functions = [(
+            String::from("y"),
+            sum(vec![vec![l(0, true), l(1, true)]], true),
+        )];
+        let netlist =
+            technology_map_by_nand_nor("m", &ports, &inputs, &functions, "input/library.json");
+        assert_eq!(gates(&netlist.verilog), 1);
+    }
+
+    #[test]
+    fn test_decoder_lines() {
+        let ports = [
+            port("a", false),
+            port("b", false),
+            port("c", false),
+            port("d", false),
+            port("y", true),
+        ];
+        let inputs = names(&["a", "b", "c", "d"]);
+        let decoder = Literal::Decoder((0, true), (1, false));
+        let terms = vec![
+            vec![Literal::Input(2, true), decoder],
+            vec![Literal::Input(3, true), decoder],
+        ];
+        let functions = [(String::from("y"), sum(terms, false))];
+        assert_eq!(functions[0].1.format(&inputs), "f = <c><a|b'> + <d><a|b'>");
+        let netlist =
+            technology_map_by_nand_nor("m", &ports, &inputs, &functions, "input/library.json")
+                .verilog;
+
+        /* a|b' is decoded once for both products, and b inverted once */
+        let uses = |name: &str| {
+            netlist.matches(&format!("({}, ", name)).count()
+                + netlist.matches(&format!(", {}, ", name)).count()
+        };
+        assert_eq!(uses("a"), 1);
+        assert_eq!(netlist.matches("(b, b, ").count(), 1);
+    }
+
+    #[test]
+    fn test_netlist_functions() {
+        let ports = [
+            port("a", false),
+            port("b", false),
+            port("c", false),
+            port("x", true),
+            port("y", true),
+            port("z", true),
+        ];
+        let inputs = names(&["a", "b", "c"]);
+        let functions = [
+            (
+                String::from("x"),
+                sum(
+                    vec![
+                        vec![Literal::Input(0, true), Literal::Input(1, false)],
+                        vec![Literal::Input(2, true)],
+                    ],
+                    false,
+                ),
+            ),
+            (
+                String::from("y"),
+                sum(
+                    vec![vec![
+                        Literal::Input(2, false),
+                        Literal::Decoder((0, true), (1, false)),
+                    ]],
+                    true,
+                ),
+            ),
+            (String::from("z"), Function::Constant(true)),
+        ];
+        let netlist =
+            technology_map_by_nand_nor("m", &ports, &inputs, &functions, "input/library.json");
+
+        let mut m = Manager::new();
+        let (a, b, c) = (m.var("a"), m.var("b"), m.var("c"));
+        let t = m.and(a, !b);
+        let x = m.or(t, c);
+        let t = m.or(a, !b);
+        let y = !m.and(!c, t);
+        let mapped = netlist.functions(&mut m);
+        assert_eq!(mapped.len(), 3);
+        for (target, f) in [("x", x), ("y", y), ("z", Bdd::TRUE)] {
+            assert!(mapped.contains(&(target.to_string(), f)));
+        }
+
+        /* the negative y is right as an inverted sum and as a product of sums */
+        for de_morgan in [false, true] {
+            let dag = transform_boolean_algebra_to_dag(&inputs, &functions, de_morgan);
+            let netlist = Netlist {
+                verilog: String::new(),
+                dag: cancel_double_inversions(straightforward_map("input/library.json", dag)),
+            };
+            assert!(netlist.functions(&mut m).contains(&(String::from("y"), y)));
+        }
+    }
+
+    #[test]
+    fn test_escaped_inputs() {
+        /* escaped names may hold the characters the printed form uses */
+        let inputs = names(&["\\a+b ", "\\<c|d'> "]);
+        let ports = [
+            port(&inputs[0], false),
+            port(&inputs[1], false),
+            port("y", true),
+        ];
+        let functions = [(
+            String::from("y"),
+            sum(
+                vec![vec![Literal::Input(0, true), Literal::Input(1, false)]],
+                false,
+            ),
+        )];
+        let netlist =
+            technology_map_by_nand_nor("m", &ports, &inputs, &functions, "input/library.json");
+
+        let mut m = Manager::new();
+        let (a, c) = (m.var(&inputs[0]), m.var(&inputs[1]));
+        let y = m.and(a, !c);
+        assert_eq!(netlist.functions(&mut m), vec![(String::from("y"), y)]);
+        assert_eq!(m.num_vars(), 2);
+    }
 }